@@ -0,0 +1,9117 @@
+//! Automatically rebases and merges dependabot PRs.
+//! Requires a personal GitHub token or GitHub App credentials.
+
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use futures::stream::{self, StreamExt};
+use http::{HeaderMap, StatusCode};
+use http_body_util::combinators::BoxBody;
+use log::{self, debug, error, info, warn};
+use octocrab::models::pulls::MergeableState;
+use octocrab::params::repos::Reference;
+use octocrab::{FromResponse, Octocrab, Page};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::OnceCell;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Falls back to the `GITHUB_TOKEN` environment variable when absent or empty.
+    pub github_token: Option<String>,
+    /// Read the token from this file instead, e.g. a mounted container secret.
+    pub github_token_file: Option<String>,
+    /// Authenticate as a GitHub App instead of with a personal token.
+    pub auth: Option<AppAuthConfig>,
+    /// GitHub Enterprise Server API, e.g. `https://github.example.com/api/v3`.
+    /// Defaults to `https://api.github.com`.
+    pub api_url: Option<String>,
+    /// `owner/*` entries expand to every repo of the owner that isn't
+    /// archived or disabled, on every run.
+    pub repos: Vec<RepoConfig>,
+    /// Repos left out when expanding `*` entries in `repos`, e.g.
+    /// `org/legacy-*`.
+    #[serde(default)]
+    pub exclude_repos: Vec<String>,
+    /// The largest version bump that is merged automatically. Unlimited when unset.
+    pub max_bump: Option<Bump>,
+    /// The highest version each of these dependencies is merged up to,
+    /// e.g. `{ tokio = "1.999" }` holds back tokio 2.0.0.
+    #[serde(default)]
+    pub version_ceilings: HashMap<String, String>,
+    /// Which of several mergeable PRs goes first. In the order GitHub lists
+    /// them, newest first, when unset.
+    pub merge_order: Option<MergeOrder>,
+    /// Merge and rebase security updates before any other PRs, whatever
+    /// the `merge_order`.
+    #[serde(default = "default_security_updates_first")]
+    pub security_updates_first: bool,
+    /// Don't make security updates wait out `min_age_hours`.
+    #[serde(default)]
+    pub security_updates_skip_min_age: bool,
+    /// GitHub uses the repository default when unset.
+    pub merge_method: Option<MergeMethod>,
+    /// What's done to PRs ready to be merged.
+    #[serde(default)]
+    pub action: RepoAction,
+    /// Title of merge commits, e.g. `"Bump {dependency} to {new_version} (#{pr_number})"`.
+    /// GitHub generates one when unset.
+    pub merge_commit_title: Option<String>,
+    /// Body of merge commits, with the placeholders of `merge_commit_title`.
+    pub merge_commit_message: Option<String>,
+    /// Merge versions like `2.0.0-rc.1` too.
+    #[serde(default)]
+    pub allow_prereleases: bool,
+    /// What to do with PRs bumping to a version that couldn't be parsed,
+    /// e.g. an action's branch name, which `allow_prereleases`,
+    /// `version_ceilings` and `max_bump` can't judge.
+    #[serde(default)]
+    pub unparsed_versions: UnparsedVersionPolicy,
+    /// Check runs that must be present and successful before merging.
+    #[serde(default)]
+    pub required_checks: Vec<String>,
+    /// Check run conclusions that keep PRs from being merged. Runs that
+    /// haven't completed do too.
+    #[serde(default = "default_blocking_conclusions")]
+    pub blocking_conclusions: Vec<String>,
+    /// PRs with fewer successful check runs and commit statuses than this
+    /// aren't merged, so that a misconfigured CI reporting nothing doesn't
+    /// count as green.
+    #[serde(default)]
+    pub min_checks: usize,
+    /// Dependencies that are never merged automatically. `*` matches
+    /// any characters, e.g. `aws-sdk-*`.
+    #[serde(default)]
+    pub ignore_dependencies: Vec<String>,
+    /// Only PRs for these ecosystems are merged, e.g. `["github_actions",
+    /// "docker"]`. Matched against the package manager in dependabot's
+    /// branch names, e.g. `pip`, and its ecosystem labels, e.g. `python`.
+    /// All ecosystems when empty.
+    #[serde(default)]
+    pub ecosystems: Vec<String>,
+    /// PRs for these ecosystems are never merged automatically.
+    #[serde(default)]
+    pub ignore_ecosystems: Vec<String>,
+    /// Only PRs that carry all of these labels are merged, e.g. an
+    /// `automerge` label applied by a human.
+    #[serde(default)]
+    pub require_labels: Vec<String>,
+    /// PRs with any of these labels are left alone.
+    #[serde(default)]
+    pub exclude_labels: Vec<String>,
+    /// Like `exclude_labels`, and also keeps `close_stale_after_days` from
+    /// closing the PR.
+    #[serde(default = "default_hold_labels")]
+    pub hold_labels: Vec<String>,
+    /// Close PRs that are older than this and can't be merged because their
+    /// checks keep failing or their conflicts need manual resolution, with a
+    /// comment why. PRs the config holds back, e.g. by `max_bump`, stay open,
+    /// and so do all PRs when unset.
+    pub close_stale_after_days: Option<i64>,
+    /// Only PRs targeting these branches are merged, e.g. `["main"]` or
+    /// `["release/*"]`. All branches when empty.
+    #[serde(default)]
+    pub base_branches: Vec<String>,
+    /// Only PRs with a title matching this regex are merged.
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub title_allow_regex: Option<Regex>,
+    /// PRs with a title matching this regex are left alone.
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub title_deny_regex: Option<Regex>,
+    /// Finds the new version in PR titles dependabot's usual "Bump foo from
+    /// 1.0.0 to 1.0.1" doesn't fit, in capture group 1, e.g.
+    /// `"auf (\\S+) aktualisiert"`. Titles it doesn't match are parsed as usual.
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub version_regex: Option<Regex>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Requests fail when connecting or waiting for a response takes
+    /// longer than this, and are retried when that's safe.
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Comment that asks dependabot to rebase a PR.
+    #[serde(default = "default_rebase_command")]
+    pub rebase_command: String,
+    /// Once dependabot failed to rebase a PR this many times, ask it to
+    /// `@dependabot recreate` the PR instead, once. Until then failed
+    /// rebases are retried. When unset, PRs dependabot couldn't rebase are
+    /// left for humans.
+    pub recreate_after_failed_rebases: Option<usize>,
+    /// Ask dependabot to `@dependabot recreate` PRs with merge conflicts,
+    /// once, as rebasing often can't resolve conflicts in lockfiles. PRs
+    /// still conflicting after that are left for humans.
+    #[serde(default = "default_recreate_conflicting")]
+    pub recreate_conflicting: bool,
+    /// A rebase isn't requested again while an earlier `rebase_command`
+    /// comment is younger than this.
+    #[serde(default = "default_rebase_comment_lookback_minutes")]
+    pub rebase_comment_lookback_minutes: i64,
+    /// After requesting a rebase, keep polling the PR and merge it in the
+    /// same run once it's rebased and green. Makes runs take longer.
+    #[serde(default)]
+    pub wait_for_rebase: bool,
+    /// How long `wait_for_rebase` waits before leaving the PR to the next run.
+    #[serde(default = "default_wait_for_rebase_timeout_secs")]
+    pub wait_for_rebase_timeout_secs: u64,
+    /// Caps the merges across all repos in one run, to avoid a burst of
+    /// CI builds on the base branches. Unlimited when unset.
+    pub max_merges_per_run: Option<usize>,
+    /// Seconds to wait between merges across all repos in one run, to
+    /// spread out the CI builds they trigger. No waiting when unset.
+    pub merge_cooldown_seconds: Option<u64>,
+    /// Runs don't start with fewer core API requests left than this, so
+    /// they don't die halfway through. Unchecked when unset.
+    pub min_rate_limit: Option<u64>,
+    /// Exit code of a one-off run that found no dependabot PRs at all, so
+    /// monitoring can tell a quiet day from a blocked backlog.
+    #[serde(default)]
+    pub no_prs_exit_code: u8,
+    /// Exit code of a one-off run that found PRs but skipped all of them.
+    #[serde(default)]
+    pub all_skipped_exit_code: u8,
+    /// How many repos are processed at the same time. Kept low by default
+    /// to stay clear of GitHub's secondary rate limits.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Only merge PRs that contain the latest base branch commit and ask
+    /// dependabot to rebase the others, as GitHub's "require branches to
+    /// be up to date" protection does. When off, only conflicting PRs are
+    /// rebased.
+    #[serde(default = "default_require_up_to_date")]
+    pub require_up_to_date: bool,
+    /// Read the PRs of a repo with their checks, reviews and base branch in
+    /// one GraphQL query per page of PRs, instead of several REST requests
+    /// per PR. `require_up_to_date` still takes a request per PR.
+    #[serde(default)]
+    pub use_graphql: bool,
+    /// Approve PRs before merging them. Turn off for repos that don't
+    /// require reviews or forbid approving your own PRs.
+    #[serde(default = "default_auto_approve")]
+    pub auto_approve: bool,
+    /// Add PRs to the base branch's merge queue when it has one, instead of
+    /// merging them directly. PRs that are queued already or have
+    /// auto-merge enabled are left alone.
+    #[serde(default)]
+    pub use_merge_queue: bool,
+    /// Enable GitHub's auto-merge on PRs instead of merging them directly,
+    /// so GitHub merges them once branch protection is satisfied. Falls
+    /// back to merging directly when auto-merge can't be enabled.
+    #[serde(default)]
+    pub enable_auto_merge: bool,
+    /// Comment `@dependabot merge`, or `@dependabot squash and merge` with
+    /// `merge_method = "squash"`, instead of merging PRs through the API, for
+    /// repos where only dependabot may merge its PRs.
+    #[serde(default)]
+    pub merge_via_comment: bool,
+    /// Delete the PR branch after merging, for repos that don't have
+    /// GitHub's automatic branch deletion turned on.
+    #[serde(default)]
+    pub delete_branch_after_merge: bool,
+    /// PRs younger than this are left for humans to review first.
+    #[serde(default)]
+    pub min_age_hours: i64,
+    /// Merge PRs with commits by authors other than `bot_login`, e.g. a
+    /// human fixing a conflict. Those would be approved automatically too.
+    #[serde(default)]
+    pub allow_human_commits: bool,
+    /// Comment on PRs why they're held back by the policies, e.g. their
+    /// labels or bump size, once per reason.
+    #[serde(default)]
+    pub comment_skip_reasons: bool,
+    /// Leave every repo alone, e.g. during a release freeze. `--pause`
+    /// sets this too.
+    #[serde(default)]
+    pub paused: bool,
+    /// Only merge during these hours. Merging is always allowed when unset.
+    pub merge_window: Option<MergeWindow>,
+    /// Remember what was done to which PR across runs in this JSON file,
+    /// e.g. to notice PRs dependabot doesn't rebase. Runs are stateless
+    /// when unset.
+    pub state_file: Option<String>,
+    /// Hold back a dependency for this long when the checks of the commit
+    /// merging its update fail, which later runs look at. Needs `state_file`.
+    pub quarantine_hours: Option<i64>,
+    /// Write a JSON `RunReport` here after every run.
+    pub report_file: Option<String>,
+    /// Every merge and merge failure is POSTed here as `{ "text": ... }`,
+    /// which is what Slack incoming webhooks expect.
+    pub notify_webhook: Option<String>,
+    /// Serve Prometheus metrics at `http://<metrics_addr>/metrics` in
+    /// `--watch` mode, e.g. `127.0.0.1:9090`.
+    pub metrics_addr: Option<String>,
+    /// Author login(s) of the PRs to handle: a single string or a list.
+    #[serde(
+        default = "default_bot_login",
+        deserialize_with = "deserialize_one_or_many"
+    )]
+    pub bot_login: Vec<String>,
+}
+
+fn default_security_updates_first() -> bool {
+    true
+}
+
+fn default_request_timeout_secs() -> u64 {
+    30
+}
+
+fn default_blocking_conclusions() -> Vec<String> {
+    [
+        "failure",
+        "cancelled",
+        "timed_out",
+        "action_required",
+        "stale",
+        "startup_failure",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+fn default_recreate_conflicting() -> bool {
+    true
+}
+
+fn default_rebase_command() -> String {
+    "@dependabot rebase".to_string()
+}
+
+fn default_rebase_comment_lookback_minutes() -> i64 {
+    60
+}
+
+fn default_wait_for_rebase_timeout_secs() -> u64 {
+    900
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_auto_approve() -> bool {
+    true
+}
+
+fn default_hold_labels() -> Vec<String> {
+    vec!["on-hold".to_string(), "wontfix".to_string()]
+}
+
+fn default_require_up_to_date() -> bool {
+    true
+}
+
+fn default_bot_login() -> Vec<String> {
+    vec!["dependabot[bot]".to_string()]
+}
+
+/// Compiles the regex while parsing, so a bad one is reported at startup.
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+fn deserialize_one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppAuthConfig {
+    app_id: u64,
+    installation_id: u64,
+    /// PEM encoded RSA private key of the app.
+    private_key_path: String,
+}
+
+/// When merges may happen, e.g. business hours.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MergeWindow {
+    /// IANA name, e.g. `Europe/Vilnius`.
+    #[serde(default = "default_timezone")]
+    timezone: Tz,
+    /// Local days of the week, e.g. `["Mon", "Tue"]`. Every day when unset.
+    #[serde(default = "default_days")]
+    days: Vec<Weekday>,
+    /// Local hour the window opens, inclusive.
+    #[serde(default)]
+    start_hour: u32,
+    /// Local hour the window closes, exclusive. Windows spanning midnight
+    /// end before they start, e.g. 22 to 6.
+    #[serde(default = "default_end_hour")]
+    end_hour: u32,
+    /// Keep requesting rebases outside the window, so that PRs are ready
+    /// to merge when it opens.
+    #[serde(default = "default_rebase_outside")]
+    rebase_outside: bool,
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+fn default_days() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+}
+
+fn default_end_hour() -> u32 {
+    24
+}
+
+fn default_rebase_outside() -> bool {
+    true
+}
+
+impl MergeWindow {
+    fn contains(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        let hour = local.hour();
+        let in_hours = if self.start_hour <= self.end_hour {
+            self.start_hour <= hour && hour < self.end_hour
+        } else {
+            self.start_hour <= hour || hour < self.end_hour
+        };
+        in_hours && self.days.contains(&local.weekday())
+    }
+}
+
+/// How the tool authenticates against GitHub.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    Token(String),
+    App {
+        app_id: u64,
+        installation_id: u64,
+        private_key_path: String,
+    },
+}
+
+/// Retries of GitHub API calls failing with 5xx or rate limit errors.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    max_retries: u32,
+    /// Doubled on every subsequent retry.
+    base_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 1000,
+        }
+    }
+}
+
+impl RetryConfig {
+    fn backoff(&self, attempt: u32) -> Duration {
+        Duration::from_millis(self.base_delay_ms.saturating_mul(1 << attempt.min(16)))
+    }
+}
+
+/// An entry in `repos`: either a bare `"org/repo"` string or a table
+/// overriding global settings, e.g. `{ name = "org/repo", merge_method = "squash" }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "an `owner/name` string or a table with `name` and known per-repo settings"
+)]
+pub enum RepoConfig {
+    Name(String),
+    Table(Box<RepoTable>),
+}
+
+/// Per-repo overrides. Unset fields fall back to the global setting of
+/// the same name.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoTable {
+    pub name: String,
+    pub max_bump: Option<Bump>,
+    pub merge_method: Option<MergeMethod>,
+    pub action: Option<RepoAction>,
+    pub required_checks: Option<Vec<String>>,
+    pub min_checks: Option<usize>,
+    pub ignore_dependencies: Option<Vec<String>>,
+    pub ecosystems: Option<Vec<String>>,
+    pub ignore_ecosystems: Option<Vec<String>>,
+    pub require_labels: Option<Vec<String>>,
+    pub exclude_labels: Option<Vec<String>>,
+    pub base_branches: Option<Vec<String>>,
+    pub min_age_hours: Option<i64>,
+    /// Leave the repo alone without removing it from `repos`.
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl RepoConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            RepoConfig::Name(name) => name,
+            RepoConfig::Table(table) => &table.name,
+        }
+    }
+
+    fn is_wildcard(&self) -> bool {
+        self.name().contains('*')
+    }
+
+    /// The same settings for another repo.
+    fn with_name(&self, name: String) -> RepoConfig {
+        match self {
+            RepoConfig::Name(_) => RepoConfig::Name(name),
+            RepoConfig::Table(table) => RepoConfig::Table(Box::new(RepoTable {
+                name,
+                ..(**table).clone()
+            })),
+        }
+    }
+}
+
+/// The settings in effect for a single repo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoSettings {
+    pub name: String,
+    pub max_bump: Option<Bump>,
+    pub merge_method: Option<MergeMethod>,
+    pub action: RepoAction,
+    pub required_checks: Vec<String>,
+    pub min_checks: usize,
+    pub ignore_dependencies: Vec<String>,
+    pub ecosystems: Vec<String>,
+    pub ignore_ecosystems: Vec<String>,
+    pub require_labels: Vec<String>,
+    pub exclude_labels: Vec<String>,
+    pub base_branches: Vec<String>,
+    pub min_age_hours: i64,
+    pub paused: bool,
+}
+
+impl Config {
+    pub fn repo_settings(&self, repo: &RepoConfig) -> RepoSettings {
+        let table = match repo {
+            RepoConfig::Name(name) => RepoTable {
+                name: name.clone(),
+                ..RepoTable::default()
+            },
+            RepoConfig::Table(table) => (**table).clone(),
+        };
+        RepoSettings {
+            name: table.name,
+            max_bump: table.max_bump.or(self.max_bump),
+            merge_method: table.merge_method.or(self.merge_method),
+            action: table.action.unwrap_or(self.action),
+            required_checks: table
+                .required_checks
+                .unwrap_or_else(|| self.required_checks.clone()),
+            min_checks: table.min_checks.unwrap_or(self.min_checks),
+            ignore_dependencies: table
+                .ignore_dependencies
+                .unwrap_or_else(|| self.ignore_dependencies.clone()),
+            ecosystems: table.ecosystems.unwrap_or_else(|| self.ecosystems.clone()),
+            ignore_ecosystems: table
+                .ignore_ecosystems
+                .unwrap_or_else(|| self.ignore_ecosystems.clone()),
+            require_labels: table
+                .require_labels
+                .unwrap_or_else(|| self.require_labels.clone()),
+            exclude_labels: table
+                .exclude_labels
+                .unwrap_or_else(|| self.exclude_labels.clone()),
+            base_branches: table
+                .base_branches
+                .unwrap_or_else(|| self.base_branches.clone()),
+            min_age_hours: table.min_age_hours.unwrap_or(self.min_age_hours),
+            paused: self.paused || table.paused,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoAction {
+    #[default]
+    Merge,
+    /// Approve PRs ready to be merged and leave the merge to others, e.g.
+    /// a merge queue or a policy engine.
+    ApproveOnly,
+    /// Only keep PRs rebased.
+    RebaseOnly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMethod {
+    Merge,
+    Squash,
+    Rebase,
+}
+
+/// Magnitude of a version change. Ordered from the least to the most risky.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnparsedVersionPolicy {
+    /// Treat them like any other PR.
+    Merge,
+    #[default]
+    Skip,
+    /// Skip them and comment that they need a look from a human, even
+    /// without `comment_skip_reasons`.
+    RequireManual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeOrder {
+    OldestFirst,
+    NewestFirst,
+    /// Patches before minor before major bumps, unknown ones last.
+    SmallestBumpFirst,
+}
+
+#[derive(Debug, Clone)]
+pub struct Repo {
+    pub org: String,
+    pub repo: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DependabotPr {
+    pub url: String,
+    pub number: u64,
+    pub repo: Repo,
+    /// GraphQL ID.
+    pub node_id: String,
+    pub head_ref: String,
+    pub head_sha: String,
+
+    pub all_checks_pass: bool,
+    // PR rebased off a base branch.
+    pub rebased: bool,
+    pub rebase_in_progress: bool,
+    // Dependabot gave up on the last rebase, a human needs to step in.
+    pub rebase_failed: bool,
+    /// How often dependabot gave up on rebasing the PR.
+    pub failed_rebases: usize,
+    pub recreate_requested: bool,
+    pub draft: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub mergeable: Option<bool>,
+    pub mergeable_state: Option<MergeableState>,
+    pub labels: Vec<String>,
+    /// Fixes a known vulnerability.
+    pub is_security: bool,
+
+    pub dependency_name: String,
+    pub old_version: String,
+    pub new_version: String,
+    // A grouped update bumping several dependencies at once.
+    pub is_group: bool,
+    pub group_updates: Vec<DependencyUpdate>,
+    // Set once this tool has merged the PR.
+    pub merge_sha: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DependencyUpdate {
+    pub name: String,
+    pub old_version: String,
+    pub new_version: String,
+}
+
+impl DependabotPr {
+    /// Every dependency bumped by this PR, so policies apply to each
+    /// member of a group. Empty for groups whose body couldn't be parsed.
+    fn updates(&self) -> Vec<DependencyUpdate> {
+        if self.is_group {
+            return self.group_updates.clone();
+        }
+        vec![DependencyUpdate {
+            name: self.dependency_name.clone(),
+            old_version: self.old_version.clone(),
+            new_version: self.new_version.clone(),
+        }]
+    }
+
+    /// What dependabot says the PR is for: the package manager in its branch
+    /// name, e.g. `pip`, and its ecosystem labels, e.g. `python`. Other
+    /// labels like `dependencies` don't count.
+    fn ecosystems(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .labels
+            .iter()
+            .map(String::as_str)
+            .filter(|label| ECOSYSTEM_LABELS.contains(label))
+            .collect();
+        // Dependabot's branches are `dependabot/<ecosystem>/<directory>/...`.
+        if let Some((ecosystem, _)) = self
+            .head_ref
+            .strip_prefix("dependabot/")
+            .and_then(|rest| rest.split_once('/'))
+        {
+            names.push(ecosystem);
+        }
+        names
+    }
+
+    /// The biggest bump among the updates, unknown if any of them is.
+    fn bump(&self) -> Option<Bump> {
+        self.updates()
+            .iter()
+            .map(|update| bump_level(&update.old_version, &update.new_version))
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .max()
+    }
+
+    /// GitHub can't create a merge commit, dependabot has to rebase first.
+    fn has_conflicts(&self) -> bool {
+        self.mergeable == Some(false) || self.mergeable_state == Some(MergeableState::Dirty)
+    }
+
+    fn needs_rebase(&self) -> bool {
+        !self.rebased || self.has_conflicts()
+    }
+
+    fn ready_to_merge(&self) -> bool {
+        self.all_checks_pass && self.rebased && !self.has_conflicts()
+    }
+
+    /// Why an otherwise eligible PR can't be merged yet.
+    fn skip_reason(&self) -> Option<&'static str> {
+        if self.has_conflicts() {
+            Some("merge conflicts")
+        } else if !self.rebased {
+            Some("not rebased")
+        } else if !self.all_checks_pass {
+            Some("checks failing")
+        } else {
+            None
+        }
+    }
+
+    /// Identifies the PR in the `State`.
+    fn key(&self) -> String {
+        format!("{}/{}#{}", self.repo.org, self.repo.repo, self.number)
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+struct IgnoreResp {}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MergeResult {
+    /// The merge commit.
+    pub sha: String,
+}
+
+/// Every decision made during a run, for dashboards and monitoring.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunReport {
+    pub dry_run: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_usage: Option<ApiUsage>,
+    pub repos: Vec<RepoReport>,
+}
+
+/// API requests used during a run, by any user of the token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiUsage {
+    pub core: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graphql: Option<u64>,
+}
+
+impl ApiUsage {
+    fn between(before: &RateLimits, after: &RateLimits) -> Self {
+        let (before, after) = (&before.resources, &after.resources);
+        Self {
+            core: after.core.used_since(&before.core),
+            graphql: before
+                .graphql
+                .as_ref()
+                .zip(after.graphql.as_ref())
+                .map(|(before, after)| after.used_since(before)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RepoReport {
+    pub repo: String,
+    pub prs_found: usize,
+    pub duration_secs: f64,
+    pub events: Vec<PrEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Left alone because of `paused`.
+    pub paused: bool,
+    /// The token has no write permission, so PRs were only approved.
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PrEvent {
+    pub url: String,
+    pub action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Skipped,
+    Rebased,
+    Approved,
+    Merged,
+    Queued,
+    AutoMerge,
+    Closed,
+    Failed,
+}
+
+impl Action {
+    /// Same as the serialized name.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Skipped => "skipped",
+            Action::Rebased => "rebased",
+            Action::Approved => "approved",
+            Action::Merged => "merged",
+            Action::Queued => "queued",
+            Action::AutoMerge => "auto_merge",
+            Action::Closed => "closed",
+            Action::Failed => "failed",
+        }
+    }
+}
+
+/// What a run amounted to, see [`RunReport::outcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// No repo has any dependabot PRs open.
+    NoPrs,
+    /// There are PRs, but none of them was acted on.
+    AllSkipped,
+    /// Every repo is paused.
+    Paused,
+    /// Something was merged, approved, rebased, closed or failed.
+    Active,
+}
+
+impl RunReport {
+    pub fn failed_repos(&self) -> usize {
+        self.repos
+            .iter()
+            .filter(|repo| repo.error.is_some())
+            .count()
+    }
+
+    pub fn prs_found(&self) -> usize {
+        self.repos.iter().map(|repo| repo.prs_found).sum()
+    }
+
+    /// Failed repos make a run active, since their PRs are unknown.
+    pub fn outcome(&self) -> RunOutcome {
+        if self.failed_repos() > 0 {
+            return RunOutcome::Active;
+        }
+        let acted = self
+            .repos
+            .iter()
+            .flat_map(|repo| repo.events.iter())
+            .any(|event| event.action != Action::Skipped);
+        if acted {
+            RunOutcome::Active
+        } else if !self.repos.is_empty() && self.repos.iter().all(|repo| repo.paused) {
+            RunOutcome::Paused
+        } else if self.prs_found() == 0 {
+            RunOutcome::NoPrs
+        } else {
+            RunOutcome::AllSkipped
+        }
+    }
+}
+
+impl RepoReport {
+    pub fn new(repo: &str) -> Self {
+        Self {
+            repo: repo.to_string(),
+            ..Self::default()
+        }
+    }
+
+    fn record(&mut self, url: &str, action: Action, reason: Option<String>) {
+        self.events.push(PrEvent {
+            url: url.to_string(),
+            action,
+            reason,
+        });
+    }
+
+    fn skip(&mut self, url: &str, reason: impl Into<String>) {
+        let reason = reason.into();
+        info!("Skipping {:?}: {}", url, reason);
+        self.record(url, Action::Skipped, Some(reason));
+    }
+
+    /// PRs skipped for any reason, each counted once.
+    fn skipped_prs(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|event| event.action == Action::Skipped)
+            .map(|event| event.url.as_str())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+/// Why checking a repo's PRs failed.
+#[derive(Debug, thiserror::Error)]
+pub enum AutomergeError {
+    #[error("invalid config: {0}")]
+    Config(String),
+    /// GitHub rejected the credentials.
+    #[error("authentication failed: {0}")]
+    Auth(String),
+    #[error(transparent)]
+    GitHub(#[from] octocrab::Error),
+    /// GraphQL errors come with a 200 status.
+    #[error("GraphQL error: {0}")]
+    GraphQl(String),
+    #[error("unexpected response: {0}")]
+    Parse(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl AutomergeError {
+    /// Status of a failed GitHub API request.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            AutomergeError::GitHub(octocrab::Error::GitHub { source, .. }) => {
+                Some(source.status_code)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether GitHub turned the request down for exceeding a rate limit,
+    /// in which case trying again later may succeed.
+    pub fn is_rate_limited(&self) -> bool {
+        match self {
+            AutomergeError::GitHub(e) => {
+                self.status() == Some(StatusCode::TOO_MANY_REQUESTS) || is_secondary_rate_limit(e)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether GitHub rejected the credentials, e.g. because the token
+    /// expired or was revoked.
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, AutomergeError::Auth(_)) || self.status() == Some(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// The GitHub operations behind the merge decisions, so that those can be
+/// tested without a server.
+// Runs don't spawn these futures, so they needn't be `Send`.
+#[allow(async_fn_in_trait)]
+pub trait GitHubApi {
+    /// Open PRs of `cfg.bot_login` that pass the repo's filters.
+    async fn dependabot_prs(
+        &self,
+        cfg: &Config,
+        settings: &RepoSettings,
+        report: &mut RepoReport,
+    ) -> Result<Vec<DependabotPr>, AutomergeError>;
+    /// Every repo of an organization or user.
+    async fn owner_repos(&self, owner: &str) -> octocrab::Result<Vec<RepoInfo>>;
+    async fn comments_since(
+        &self,
+        pr: &DependabotPr,
+        since: DateTime<Utc>,
+    ) -> octocrab::Result<Vec<IssueComment>>;
+    async fn comment(&self, pr: &DependabotPr, body: &str) -> octocrab::Result<()>;
+    /// Login of the authenticated user.
+    async fn viewer(&self) -> octocrab::Result<String>;
+    async fn reviews(&self, pr: &DependabotPr) -> octocrab::Result<Vec<Review>>;
+    async fn approve(&self, pr: &DependabotPr) -> octocrab::Result<()>;
+    async fn merge(
+        &self,
+        pr: &DependabotPr,
+        merge_method: Option<MergeMethod>,
+        commit: &MergeCommit,
+    ) -> octocrab::Result<MergeResult>;
+    /// Succeeds when the branch is gone already.
+    async fn delete_branch(&self, pr: &DependabotPr) -> octocrab::Result<()>;
+    async fn close(&self, pr: &DependabotPr) -> octocrab::Result<()>;
+    /// Whether the checks of a commit of `repo` passed, `None` while some
+    /// are still running.
+    async fn commit_checks_pass(
+        &self,
+        repo: &str,
+        sha: &str,
+        blocking_conclusions: &[String],
+    ) -> octocrab::Result<Option<bool>>;
+    async fn merge_queue_state(&self, pr: &DependabotPr)
+        -> Result<MergeQueueState, AutomergeError>;
+    async fn enqueue(&self, pr: &DependabotPr) -> Result<(), AutomergeError>;
+    async fn enable_auto_merge(
+        &self,
+        pr: &DependabotPr,
+        merge_method: Option<MergeMethod>,
+    ) -> Result<(), AutomergeError>;
+}
+
+/// `GitHubApi` over the REST API.
+pub struct GitHub<'a> {
+    octo: &'a Octocrab,
+    retry: &'a RetryConfig,
+}
+
+impl<'a> GitHub<'a> {
+    pub fn new(octo: &'a Octocrab, retry: &'a RetryConfig) -> Self {
+        Self { octo, retry }
+    }
+}
+
+impl GitHubApi for GitHub<'_> {
+    async fn dependabot_prs(
+        &self,
+        cfg: &Config,
+        settings: &RepoSettings,
+        report: &mut RepoReport,
+    ) -> Result<Vec<DependabotPr>, AutomergeError> {
+        dependabot_prs_passing_checks(self.octo, cfg, settings, report).await
+    }
+
+    async fn owner_repos(&self, owner: &str) -> octocrab::Result<Vec<RepoInfo>> {
+        let url = format!("/orgs/{}/repos?per_page=100", owner);
+        let mut page: Page<RepoInfo> =
+            match send_with_retry(self.retry, true, || self.octo._get(url.as_str())).await {
+                Ok(page) => page,
+                // Not an organization.
+                Err(octocrab::Error::GitHub { source, .. })
+                    if source.status_code == StatusCode::NOT_FOUND =>
+                {
+                    let url = format!("/users/{}/repos?per_page=100", owner);
+                    send_with_retry(self.retry, true, || self.octo._get(url.as_str())).await?
+                }
+                Err(e) => return Err(e),
+            };
+        let mut repos = page.take_items();
+        while let Some(next) = page.next.take() {
+            page = send_with_retry(self.retry, true, || self.octo._get(next.clone())).await?;
+            repos.append(&mut page.take_items());
+        }
+        Ok(repos)
+    }
+
+    async fn comments_since(
+        &self,
+        pr: &DependabotPr,
+        since: DateTime<Utc>,
+    ) -> octocrab::Result<Vec<IssueComment>> {
+        pr_comments_since(self.octo, pr, since, self.retry).await
+    }
+
+    async fn comment(&self, pr: &DependabotPr, body: &str) -> octocrab::Result<()> {
+        let url = format!(
+            "/repos/{}/{}/issues/{}/comments",
+            pr.repo.org, pr.repo.repo, pr.number
+        );
+        let comment_body = serde_json::json!({ "body": body });
+        // Not idempotent: a repeated POST would comment twice. Rate limits
+        // are still retried after their `Retry-After`.
+        let _resp: IgnoreResp = send_with_retry(self.retry, false, || {
+            self.octo._post(url.as_str(), Some(&comment_body))
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn viewer(&self) -> octocrab::Result<String> {
+        let user: User = send_with_retry(self.retry, true, || self.octo._get("/user")).await?;
+        Ok(user.login)
+    }
+
+    async fn reviews(&self, pr: &DependabotPr) -> octocrab::Result<Vec<Review>> {
+        let url = format!(
+            "/repos/{}/{}/pulls/{}/reviews?per_page=100",
+            pr.repo.org, pr.repo.repo, pr.number
+        );
+        send_with_retry(self.retry, true, || self.octo._get(url.as_str())).await
+    }
+
+    async fn approve(&self, pr: &DependabotPr) -> octocrab::Result<()> {
+        let url = format!(
+            "/repos/{}/{}/pulls/{}/reviews",
+            pr.repo.org, pr.repo.repo, pr.number
+        );
+        let review_body = serde_json::json!({
+            "event": "APPROVE"
+        });
+        // Not idempotent: a repeated POST would submit a second review.
+        let _resp: IgnoreResp = send_with_retry(self.retry, false, || {
+            self.octo._post(url.as_str(), Some(&review_body))
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn merge(
+        &self,
+        pr: &DependabotPr,
+        merge_method: Option<MergeMethod>,
+        commit: &MergeCommit,
+    ) -> octocrab::Result<MergeResult> {
+        let url = format!(
+            "/repos/{}/{}/pulls/{}/merge",
+            pr.repo.org, pr.repo.repo, pr.number
+        );
+        let mut merge_body = serde_json::json!({});
+        if let Some(merge_method) = merge_method {
+            merge_body["merge_method"] = serde_json::json!(merge_method);
+        }
+        if let Some(title) = &commit.title {
+            merge_body["commit_title"] = serde_json::json!(title);
+        }
+        if let Some(message) = &commit.message {
+            merge_body["commit_message"] = serde_json::json!(message);
+        }
+        // Not idempotent: a retry after GitHub merged would fail as not
+        // mergeable and be reported as a failed merge.
+        send_with_retry(self.retry, false, || {
+            self.octo._put(url.as_str(), Some(&merge_body))
+        })
+        .await
+    }
+
+    async fn delete_branch(&self, pr: &DependabotPr) -> octocrab::Result<()> {
+        let url = format!(
+            "/repos/{}/{}/git/refs/heads/{}",
+            pr.repo.org, pr.repo.repo, pr.head_ref
+        );
+        match send_with_retry_raw(self.retry, true, || {
+            self.octo._delete(url.as_str(), None::<&()>)
+        })
+        .await
+        {
+            Ok(_) => Ok(()),
+            // Deleted already, e.g. by GitHub's automatic branch deletion.
+            Err(octocrab::Error::GitHub { source, .. })
+                if matches!(
+                    source.status_code,
+                    StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY
+                ) =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn close(&self, pr: &DependabotPr) -> octocrab::Result<()> {
+        let url = format!(
+            "/repos/{}/{}/pulls/{}",
+            pr.repo.org, pr.repo.repo, pr.number
+        );
+        let body = serde_json::json!({ "state": "closed" });
+        let _pr: serde_json::Value = send_with_retry(self.retry, true, || {
+            self.octo._patch(url.as_str(), Some(&body))
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn commit_checks_pass(
+        &self,
+        repo: &str,
+        sha: &str,
+        blocking_conclusions: &[String],
+    ) -> octocrab::Result<Option<bool>> {
+        let (org, repo) = repo.split_once('/').unwrap_or((repo, ""));
+        let runs = commit_checks(self.octo, org, repo, sha, self.retry).await?;
+        if runs.iter().any(|run| run.status != "completed") {
+            return Ok(None);
+        }
+        Ok(Some(checks_pass(&runs, &[], 0, blocking_conclusions)))
+    }
+
+    async fn merge_queue_state(
+        &self,
+        pr: &DependabotPr,
+    ) -> Result<MergeQueueState, AutomergeError> {
+        let data: PullRequestNode<MergeQueueState> = graphql(
+            self.octo,
+            self.retry,
+            true,
+            "query($id: ID!) {
+                node(id: $id) {
+                    ... on PullRequest {
+                        isInMergeQueue
+                        isMergeQueueEnabled
+                        autoMergeRequest { enabledAt }
+                    }
+                }
+            }",
+            serde_json::json!({ "id": pr.node_id }),
+        )
+        .await?;
+        Ok(data.node)
+    }
+
+    async fn enqueue(&self, pr: &DependabotPr) -> Result<(), AutomergeError> {
+        let _data: serde_json::Value = graphql(
+            self.octo,
+            self.retry,
+            false,
+            "mutation($id: ID!) {
+                enqueuePullRequest(input: { pullRequestId: $id }) { clientMutationId }
+            }",
+            serde_json::json!({ "id": pr.node_id }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn enable_auto_merge(
+        &self,
+        pr: &DependabotPr,
+        merge_method: Option<MergeMethod>,
+    ) -> Result<(), AutomergeError> {
+        // Without a method GitHub picks the repo's default, like the REST merge.
+        let method = merge_method.map(|m| match m {
+            MergeMethod::Merge => "MERGE",
+            MergeMethod::Squash => "SQUASH",
+            MergeMethod::Rebase => "REBASE",
+        });
+        let _data: serde_json::Value = graphql(
+            self.octo,
+            self.retry,
+            true,
+            "mutation($id: ID!, $method: PullRequestMergeMethod) {
+                enablePullRequestAutoMerge(input: { pullRequestId: $id, mergeMethod: $method }) {
+                    clientMutationId
+                }
+            }",
+            serde_json::json!({ "id": pr.node_id, "method": method }),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PullRequestNode<T> {
+    node: T,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeQueueState {
+    pub is_in_merge_queue: bool,
+    /// Whether the base branch requires a merge queue.
+    pub is_merge_queue_enabled: bool,
+    pub auto_merge_request: Option<serde_json::Value>,
+}
+
+/// Sends a GraphQL request. GitHub reports GraphQL errors with a 200
+/// status, so those are turned into errors here.
+async fn graphql<T: DeserializeOwned>(
+    octo: &Octocrab,
+    retry: &RetryConfig,
+    idempotent: bool,
+    query: &str,
+    variables: serde_json::Value,
+) -> Result<T, AutomergeError> {
+    let body = serde_json::json!({ "query": query, "variables": variables });
+    let resp: GraphQlResponse<T> =
+        send_with_retry(retry, idempotent, || octo._post("/graphql", Some(&body))).await?;
+    if !resp.errors.is_empty() {
+        let messages = resp
+            .errors
+            .into_iter()
+            .map(|e| e.message)
+            .collect::<Vec<_>>();
+        return Err(AutomergeError::GraphQl(messages.join("; ")));
+    }
+    resp.data
+        .ok_or_else(|| AutomergeError::Parse("GraphQL response has no data".to_string()))
+}
+
+/// Rebase requests without dependabot pushing anything after which the PR
+/// is left for humans.
+const STUCK_AFTER_REBASE_REQUESTS: u32 = 3;
+
+/// What was done to PRs in earlier runs, kept in `state_file`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    /// By `org/repo#number`.
+    prs: BTreeMap<String, PrState>,
+    /// Merges whose checks haven't been seen finishing yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    merges: Vec<MergeState>,
+    /// Until when dependencies are held back, by `org/repo:dependency`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    quarantined: BTreeMap<String, DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct MergeState {
+    repo: String,
+    sha: String,
+    dependencies: Vec<String>,
+    at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PrState {
+    last_action: Action,
+    at: DateTime<Utc>,
+    /// PR head when the action was taken.
+    head_sha: String,
+    /// Rebases requested since dependabot last pushed to the PR.
+    #[serde(default)]
+    rebase_requests: u32,
+    /// Humans were notified that the PR is stuck.
+    #[serde(default)]
+    escalated: bool,
+}
+
+impl State {
+    /// A missing or unreadable file is an empty state: it mostly saves API
+    /// requests and comments, only quarantines are lost.
+    pub fn load(path: &str) -> Self {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Ignoring invalid state file {}: {}", path, e);
+                State::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(e) => {
+                warn!("Failed to read state file {}: {}", path, e);
+                State::default()
+            }
+        }
+    }
+
+    /// Replaces the file at once, so an interrupted run can't corrupt it.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp, path)
+    }
+
+    fn record(&mut self, pr: &DependabotPr, action: Action, now: DateTime<Utc>) {
+        let entry = self.prs.entry(pr.key()).or_insert_with(|| PrState {
+            last_action: action,
+            at: now,
+            head_sha: pr.head_sha.clone(),
+            rebase_requests: 0,
+            escalated: false,
+        });
+        if entry.head_sha != pr.head_sha {
+            entry.head_sha = pr.head_sha.clone();
+            entry.rebase_requests = 0;
+            entry.escalated = false;
+        }
+        entry.last_action = action;
+        entry.at = now;
+        if action == Action::Rebased {
+            entry.rebase_requests += 1;
+        }
+    }
+
+    /// Rebases requested in a row without dependabot pushing to the PR.
+    fn rebase_requests(&self, pr: &DependabotPr) -> u32 {
+        self.prs
+            .get(&pr.key())
+            .filter(|state| state.head_sha == pr.head_sha)
+            .map_or(0, |state| state.rebase_requests)
+    }
+
+    fn rebase_requested_since(&self, pr: &DependabotPr, since: DateTime<Utc>) -> bool {
+        self.prs
+            .get(&pr.key())
+            .is_some_and(|state| state.last_action == Action::Rebased && state.at >= since)
+    }
+
+    /// Marks the PR as escalated, returning whether it wasn't already.
+    fn escalate(&mut self, pr: &DependabotPr) -> bool {
+        match self.prs.get_mut(&pr.key()) {
+            Some(state) if !state.escalated => {
+                state.escalated = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn record_merge(&mut self, pr: &DependabotPr, now: DateTime<Utc>) {
+        let Some(sha) = &pr.merge_sha else {
+            return;
+        };
+        self.merges.push(MergeState {
+            repo: format!("{}/{}", pr.repo.org, pr.repo.repo),
+            sha: sha.clone(),
+            dependencies: pr.updates().into_iter().map(|update| update.name).collect(),
+            at: now,
+        });
+    }
+
+    fn pending_merges(&self, repo: &str) -> Vec<MergeState> {
+        self.merges
+            .iter()
+            .filter(|merge| merge.repo == repo)
+            .cloned()
+            .collect()
+    }
+
+    /// Forgets the merge, quarantining its dependencies until
+    /// `quarantine_until` if it broke the base branch.
+    fn resolve_merge(&mut self, sha: &str, quarantine_until: Option<DateTime<Utc>>) {
+        let Some(i) = self.merges.iter().position(|merge| merge.sha == sha) else {
+            return;
+        };
+        let merge = self.merges.remove(i);
+        if let Some(until) = quarantine_until {
+            for dependency in merge.dependencies {
+                let key = format!("{}:{}", merge.repo, dependency);
+                self.quarantined.insert(key, until);
+            }
+        }
+    }
+
+    fn quarantined_until(
+        &self,
+        repo: &str,
+        dependency: &str,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        self.quarantined
+            .get(&format!("{}:{}", repo, dependency))
+            .copied()
+            .filter(|until| *until > now)
+    }
+
+    fn expire_quarantines(&mut self, now: DateTime<Utc>) {
+        self.quarantined.retain(|_, until| *until > now);
+    }
+
+    /// Forgets the PRs of `repo` that aren't among its `open` ones anymore.
+    fn retain_open(&mut self, repo: &str, open: &[DependabotPr]) {
+        let prefix = format!("{}#", repo);
+        self.prs
+            .retain(|key, _| !key.starts_with(&prefix) || open.iter().any(|pr| &pr.key() == key));
+    }
+}
+
+/// State shared by all repos checked in one run.
+pub struct RunContext<'a, G> {
+    github: &'a G,
+    notifier: Option<&'a Notifier>,
+    cfg: &'a Config,
+    dry_run: bool,
+    /// Merges left before `max_merges_per_run` is reached.
+    merges_left: Option<AtomicUsize>,
+    /// Login of the authenticated user, looked up on first use.
+    viewer: OnceCell<Option<String>>,
+    /// When the last merge of this run happened, for `merge_cooldown_seconds`.
+    last_merge: tokio::sync::Mutex<Option<tokio::time::Instant>>,
+    state: Option<Mutex<State>>,
+}
+
+impl<'a, G: GitHubApi> RunContext<'a, G> {
+    pub fn new(
+        github: &'a G,
+        notifier: Option<&'a Notifier>,
+        cfg: &'a Config,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            github,
+            notifier,
+            cfg,
+            dry_run,
+            merges_left: cfg.max_merges_per_run.map(AtomicUsize::new),
+            viewer: OnceCell::new(),
+            last_merge: tokio::sync::Mutex::new(None),
+            state: None,
+        }
+    }
+
+    /// Consult and update `state` during the run.
+    pub fn with_state(self, state: State) -> Self {
+        Self {
+            state: Some(Mutex::new(state)),
+            ..self
+        }
+    }
+
+    /// Runs `f` on the state, if there is one.
+    fn state<T>(&self, f: impl FnOnce(&mut State) -> T) -> Option<T> {
+        let state = self.state.as_ref()?;
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        Some(f(&mut state))
+    }
+
+    fn remember(&self, pr: &DependabotPr, action: Action) {
+        self.state(|state| state.record(pr, action, Utc::now()));
+    }
+
+    /// Waits out `merge_cooldown_seconds` since the last merge. Merges are
+    /// serialized while the returned guard is held, set it to the merge time.
+    async fn merge_cooldown(
+        &self,
+    ) -> Option<tokio::sync::MutexGuard<'_, Option<tokio::time::Instant>>> {
+        let cooldown = Duration::from_secs(self.cfg.merge_cooldown_seconds?);
+        let last_merge = self.last_merge.lock().await;
+        if let Some(last) = *last_merge {
+            let until = last + cooldown;
+            let now = tokio::time::Instant::now();
+            if now < until {
+                info!(
+                    "Waiting {}s for merge_cooldown_seconds",
+                    (until - now).as_secs()
+                );
+                tokio::time::sleep_until(until).await;
+            }
+        }
+        Some(last_merge)
+    }
+
+    /// `None` when the credentials can't tell, e.g. GitHub App
+    /// installation tokens can't read `/user`.
+    async fn viewer(&self) -> Option<&str> {
+        self.viewer
+            .get_or_init(|| async {
+                match self.github.viewer().await {
+                    Ok(login) => Some(login),
+                    Err(e) => {
+                        warn!("Couldn't look up the authenticated user: {:?}", e);
+                        None
+                    }
+                }
+            })
+            .await
+            .as_deref()
+    }
+
+    /// Reserves one of the merges left in this run.
+    fn take_merge(&self) -> bool {
+        match &self.merges_left {
+            Some(left) => left
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok(),
+            None => true,
+        }
+    }
+
+    /// Hands back a reserved merge that didn't happen.
+    fn return_merge(&self) {
+        if let Some(left) = &self.merges_left {
+            left.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Prometheus counters, accumulated over the `RunReport`s of a daemon.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    runs: u64,
+    last_run_timestamp: i64,
+    repos: BTreeMap<String, RepoMetrics>,
+}
+
+#[derive(Debug, Default)]
+struct RepoMetrics {
+    prs_evaluated: u64,
+    errors: u64,
+    actions: BTreeMap<Action, u64>,
+    last_run_duration_secs: f64,
+}
+
+impl Metrics {
+    pub fn record(&self, report: &RunReport) {
+        let mut state = self.state.lock().unwrap();
+        state.runs += 1;
+        state.last_run_timestamp = Utc::now().timestamp();
+        for repo in report.repos.iter() {
+            let metrics = state.repos.entry(repo.repo.clone()).or_default();
+            metrics.prs_evaluated += repo.prs_found as u64;
+            metrics.errors += u64::from(repo.error.is_some());
+            metrics.last_run_duration_secs = repo.duration_secs;
+            for event in repo.events.iter() {
+                *metrics.actions.entry(event.action).or_default() += 1;
+            }
+        }
+    }
+
+    /// The Prometheus text format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        let label = |repo: &str| repo.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(out, "# TYPE automerge_runs_total counter");
+        let _ = writeln!(out, "automerge_runs_total {}", state.runs);
+        let _ = writeln!(out, "# TYPE automerge_last_run_timestamp_seconds gauge");
+        let _ = writeln!(
+            out,
+            "automerge_last_run_timestamp_seconds {}",
+            state.last_run_timestamp
+        );
+        let _ = writeln!(out, "# TYPE automerge_prs_evaluated_total counter");
+        for (repo, metrics) in state.repos.iter() {
+            let _ = writeln!(
+                out,
+                "automerge_prs_evaluated_total{{repo=\"{}\"}} {}",
+                label(repo),
+                metrics.prs_evaluated
+            );
+        }
+        let _ = writeln!(out, "# TYPE automerge_prs_total counter");
+        for (repo, metrics) in state.repos.iter() {
+            for (action, count) in metrics.actions.iter() {
+                let _ = writeln!(
+                    out,
+                    "automerge_prs_total{{repo=\"{}\",action=\"{}\"}} {}",
+                    label(repo),
+                    action.as_str(),
+                    count
+                );
+            }
+        }
+        let _ = writeln!(out, "# TYPE automerge_repo_errors_total counter");
+        for (repo, metrics) in state.repos.iter() {
+            let _ = writeln!(
+                out,
+                "automerge_repo_errors_total{{repo=\"{}\"}} {}",
+                label(repo),
+                metrics.errors
+            );
+        }
+        let _ = writeln!(out, "# TYPE automerge_run_duration_seconds gauge");
+        for (repo, metrics) in state.repos.iter() {
+            let _ = writeln!(
+                out,
+                "automerge_run_duration_seconds{{repo=\"{}\"}} {}",
+                label(repo),
+                metrics.last_run_duration_secs
+            );
+        }
+        out
+    }
+}
+
+/// Answers `GET /metrics`, and 404 to anything else.
+pub async fn serve_metrics(listener: TcpListener, metrics: Arc<Metrics>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Failed to accept a metrics connection: {:?}", e);
+                continue;
+            }
+        };
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond_metrics(stream, &metrics).await {
+                debug!("Failed to serve metrics: {:?}", e);
+            }
+        });
+    }
+}
+
+async fn respond_metrics(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    // Only the request line matters, the rest of the request is ignored.
+    let mut buf = [0; 1024];
+    let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .map_err(|_| std::io::ErrorKind::TimedOut)??;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let (status, body) = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Posts merge outcomes to `notify_webhook`.
+pub struct Notifier {
+    /// Unauthenticated, so the GitHub token never reaches the webhook.
+    client: Octocrab,
+    url: String,
+}
+
+impl Notifier {
+    pub fn new(url: &str, timeout: Duration) -> Result<Self, Box<dyn Error>> {
+        let mut builder = Octocrab::builder()
+            .set_connect_timeout(Some(timeout))
+            .set_read_timeout(Some(timeout))
+            .set_write_timeout(Some(timeout));
+        builder.add_retry_config(octocrab::service::middleware::retry::RetryConfig::None);
+        Ok(Self {
+            client: builder.build()?,
+            url: url.to_string(),
+        })
+    }
+
+    /// Failures are logged only: a broken webhook shouldn't stop merging.
+    pub async fn notify(&self, pr: &DependabotPr, action: Action, error: Option<&str>) {
+        let repo = format!("{}/{}", pr.repo.org, pr.repo.repo);
+        let text = match (error, &pr.merge_sha) {
+            (Some(e), _) => format!("Failed to merge {} in {}: {}", pr.url, repo, e),
+            (None, _) if action == Action::Queued => {
+                format!("Added {} in {} to the merge queue", pr.url, repo)
+            }
+            (None, _) if action == Action::AutoMerge => {
+                format!("Enabled auto-merge for {} in {}", pr.url, repo)
+            }
+            (None, _) if action == Action::Closed => format!("Closed {} in {}", pr.url, repo),
+            (None, Some(sha)) => format!("Merged {} in {} as {}", pr.url, repo, sha),
+            (None, None) => format!("Merged {} in {}", pr.url, repo),
+        };
+        let payload = serde_json::json!({
+            "text": text,
+            "repo": repo,
+            "url": pr.url,
+            "action": action,
+            "sha": pr.merge_sha,
+            "error": error,
+        });
+        let res = async {
+            let resp = self.client._post(self.url.as_str(), Some(&payload)).await?;
+            octocrab::map_github_error(resp).await.map(drop)
+        }
+        .await;
+        if let Err(e) = res {
+            error!("Failed to notify webhook about {:?}: {:?}", pr.url, e);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IssueComment {
+    pub body: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub user: Option<User>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub login: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PrCommit {
+    /// `None` when the commit email doesn't belong to a GitHub user.
+    author: Option<User>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Review {
+    pub user: Option<User>,
+    pub state: String,
+    pub commit_id: Option<String>,
+}
+
+type Response = http::Response<BoxBody<Bytes, octocrab::Error>>;
+
+/// How often `wait_for_rebase` checks on the rebased PR.
+const REBASE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to back off after hitting the secondary rate limit. GitHub asks
+/// for at least a minute when it doesn't send `Retry-After`.
+const SECONDARY_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// Rate limit resets further away than this aren't waited for.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Response of the `/rate_limit` endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimits {
+    resources: RateLimitResources,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RateLimitResources {
+    core: RateLimit,
+    graphql: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RateLimit {
+    limit: u64,
+    remaining: u64,
+    /// Unix time in seconds.
+    reset: i64,
+}
+
+impl RateLimit {
+    /// Only counts requests since the reset when the window rolled over in
+    /// between.
+    fn used_since(&self, before: &RateLimit) -> u64 {
+        if self.reset == before.reset {
+            before.remaining.saturating_sub(self.remaining)
+        } else {
+            self.limit.saturating_sub(self.remaining)
+        }
+    }
+
+    fn reset_time(&self) -> String {
+        DateTime::<Utc>::from_timestamp(self.reset, 0)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| self.reset.to_string())
+    }
+}
+
+/// The parts of a repository we look at.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepoInfo {
+    pub full_name: String,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub disabled: bool,
+    /// Missing for GitHub App installation tokens.
+    pub permissions: Option<RepoPermissions>,
+}
+
+impl RepoInfo {
+    /// Whether the token may merge PRs, unknown without `permissions`.
+    fn can_merge(&self) -> Option<bool> {
+        self.permissions
+            .as_ref()
+            .map(|p| p.push || p.maintain || p.admin)
+    }
+}
+
+/// What the token may do in a repo.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RepoPermissions {
+    #[serde(default)]
+    pub push: bool,
+    #[serde(default)]
+    pub maintain: bool,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// How a PR head relates to its base branch tip.
+#[derive(Debug, Clone, Deserialize)]
+struct Comparison {
+    /// Base commits missing from the head.
+    behind_by: u64,
+}
+
+/// Response of the `/commits/{sha}/check-runs` endpoint.
+/// `octocrab::models::CheckRun` doesn't expose the run status.
+#[derive(Debug, Clone, Deserialize)]
+struct CheckRuns {
+    total_count: usize,
+    check_runs: Vec<CheckRun>,
+}
+
+/// Response of the `/commits/{sha}/status` endpoint, the latest status of
+/// every context reported with the legacy commit statuses API.
+#[derive(Debug, Clone, Deserialize)]
+struct CombinedStatus {
+    /// Of all contexts, `pending` when there are none.
+    state: String,
+    total_count: usize,
+    statuses: Vec<CommitStatus>,
+}
+
+impl CombinedStatus {
+    /// A check run per status. Contexts beyond the listed ones, which
+    /// nobody has, are judged by the combined state.
+    fn check_runs(&self) -> Vec<CheckRun> {
+        let mut runs: Vec<CheckRun> = self
+            .statuses
+            .iter()
+            .map(CommitStatus::as_check_run)
+            .collect();
+        if self.statuses.len() < self.total_count {
+            let combined = CommitStatus {
+                context: "combined status".to_string(),
+                state: self.state.clone(),
+            };
+            runs.push(combined.as_check_run());
+        }
+        runs
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CommitStatus {
+    context: String,
+    /// `error`, `failure`, `pending` or `success`.
+    state: String,
+}
+
+impl CommitStatus {
+    /// The check run it would be, so both are judged the same way.
+    fn as_check_run(&self) -> CheckRun {
+        let (status, conclusion) = match self.state.as_str() {
+            "success" => ("completed", Some("success")),
+            // `expected` comes from GraphQL, for required statuses not
+            // reported yet.
+            "pending" | "expected" => ("in_progress", None),
+            _ => ("completed", Some("failure")),
+        };
+        CheckRun {
+            name: self.context.clone(),
+            status: status.to_string(),
+            conclusion: conclusion.map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CheckRun {
+    name: String,
+    /// `queued`, `in_progress`, `completed`, etc.
+    status: String,
+    conclusion: Option<String>,
+}
+
+/// Logs the remaining API quota and fails when the core quota is below
+/// `min_rate_limit`. A failure to look it up doesn't stop the run.
+pub async fn check_rate_limit(
+    octo: &Octocrab,
+    cfg: &Config,
+) -> Result<Option<RateLimits>, Box<dyn Error>> {
+    let Some(limits) = fetch_rate_limits(octo, &cfg.retry).await else {
+        return Ok(None);
+    };
+
+    let core = &limits.resources.core;
+    info!(
+        "Rate limit: {}/{} core requests left, resets at {}",
+        core.remaining,
+        core.limit,
+        core.reset_time()
+    );
+    if let Some(graphql) = &limits.resources.graphql {
+        info!(
+            "Rate limit: {}/{} GraphQL requests left, resets at {}",
+            graphql.remaining,
+            graphql.limit,
+            graphql.reset_time()
+        );
+    }
+
+    match cfg.min_rate_limit {
+        Some(min) if core.remaining < min => Err(format!(
+            "Only {} core API requests left, below min_rate_limit {}, retry after {}",
+            core.remaining,
+            min,
+            core.reset_time()
+        )
+        .into()),
+        _ => Ok(Some(limits)),
+    }
+}
+
+/// Failures are logged only, the rate limit is informational.
+async fn fetch_rate_limits(octo: &Octocrab, retry: &RetryConfig) -> Option<RateLimits> {
+    // Doesn't count against the rate limit.
+    match send_with_retry(retry, true, || octo._get("/rate_limit")).await {
+        Ok(limits) => Some(limits),
+        Err(e) => {
+            warn!("Failed to get the rate limit: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Fails when GitHub rejects the credentials, which would otherwise fail
+/// every repo on its own.
+pub async fn check_credentials(octo: &Octocrab, retry: &RetryConfig) -> Result<(), AutomergeError> {
+    match send_with_retry_raw(retry, true, || octo._get("/rate_limit")).await {
+        Ok(_) => Ok(()),
+        Err(octocrab::Error::GitHub { source, .. })
+            if source.status_code == StatusCode::UNAUTHORIZED =>
+        {
+            Err(AutomergeError::Auth(format!(
+                "{}, check the token or the GitHub App credentials",
+                source.message
+            )))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replaces the `*` entries of `cfg.repos` with the matching repos. Repos
+/// listed explicitly keep their own settings. Owners that can't be listed
+/// are reported as failed repos.
+async fn expand_repos<G: GitHubApi>(
+    github: &G,
+    cfg: &Config,
+) -> (Vec<RepoConfig>, Vec<RepoReport>) {
+    let mut repos: Vec<RepoConfig> = cfg
+        .repos
+        .iter()
+        .filter(|repo| !repo.is_wildcard())
+        .cloned()
+        .collect();
+    let mut failed = Vec::new();
+    for pattern in cfg.repos.iter().filter(|repo| repo.is_wildcard()) {
+        let Some((owner, _)) = parse_repo_name(pattern.name()) else {
+            continue;
+        };
+        let owner_repos = match github.owner_repos(owner).await {
+            Ok(owner_repos) => owner_repos,
+            Err(e) => {
+                error!("[{}] Failed to list repos: {:?}", pattern.name(), e);
+                let mut report = RepoReport::new(pattern.name());
+                report.error = Some(e.to_string());
+                failed.push(report);
+                continue;
+            }
+        };
+        for repo in owner_repos {
+            if repo.archived
+                || repo.disabled
+                || !matches_pattern(pattern.name(), &repo.full_name)
+                || cfg
+                    .exclude_repos
+                    .iter()
+                    .any(|exclude| matches_pattern(exclude, &repo.full_name))
+                || repos.iter().any(|r| r.name() == repo.full_name)
+            {
+                continue;
+            }
+            repos.push(pattern.with_name(repo.full_name));
+        }
+    }
+    (repos, failed)
+}
+
+/// Checks every repo, even when some fail.
+/// `limits_before` are the rate limits at the start of the run, to report
+/// the API usage against.
+pub async fn run_once(
+    octo: &Octocrab,
+    notifier: Option<&Notifier>,
+    cfg: &Config,
+    dry_run: bool,
+    limits_before: Option<RateLimits>,
+) -> RunReport {
+    // Not even `*` repos are expanded, the run doesn't touch GitHub at all.
+    if cfg.paused {
+        info!("Paused, leaving every repo alone");
+        let repos = cfg
+            .repos
+            .iter()
+            .map(|repo| RepoReport {
+                paused: true,
+                ..RepoReport::new(repo.name())
+            })
+            .collect();
+        return RunReport {
+            dry_run,
+            api_usage: None,
+            repos,
+        };
+    }
+    let github = GitHub::new(octo, &cfg.retry);
+    let mut ctx = RunContext::new(&github, notifier, cfg, dry_run);
+    if let Some(path) = &cfg.state_file {
+        ctx = ctx.with_state(State::load(path));
+    }
+    let ctx = &ctx;
+    let (repo_configs, mut repos) = expand_repos(&github, cfg).await;
+    let checked: Vec<RepoReport> = stream::iter(repo_configs.iter())
+        .map(|repo| async move {
+            let mut report = RepoReport::new(repo.name());
+            let settings = cfg.repo_settings(repo);
+            let started = Instant::now();
+            if let Err(e) = check_prs(ctx, &settings, &mut report).await {
+                error!("[{}] Error: {:?}", repo.name(), e);
+                report.error = Some(e.to_string());
+            }
+            report.duration_secs = started.elapsed().as_secs_f64();
+            report
+        })
+        .buffered(cfg.concurrency.max(1))
+        .collect()
+        .await;
+    repos.extend(checked);
+
+    // Dry runs didn't do what they'd remember.
+    if let Some(path) = cfg.state_file.as_ref().filter(|_| !dry_run) {
+        if let Some(Err(e)) = ctx.state(|state| state.save(path)) {
+            error!("Failed to write state file {}: {:?}", path, e);
+        }
+    }
+
+    let mut api_usage = None;
+    if let Some(before) = &limits_before {
+        if let Some(after) = fetch_rate_limits(octo, &cfg.retry).await {
+            let usage = ApiUsage::between(before, &after);
+            info!(
+                "Used {} core and {} GraphQL API requests, {} core requests left",
+                usage.core,
+                usage.graphql.unwrap_or(0),
+                after.resources.core.remaining
+            );
+            api_usage = Some(usage);
+        }
+    }
+    let report = RunReport {
+        dry_run,
+        api_usage,
+        repos,
+    };
+    match report.outcome() {
+        RunOutcome::NoPrs => info!("No dependabot PRs in {} repos", report.repos.len()),
+        RunOutcome::AllSkipped => info!(
+            "Found {} dependabot PRs, none of them were acted on",
+            report.prs_found()
+        ),
+        RunOutcome::Paused | RunOutcome::Active => {}
+    }
+    report
+}
+
+/// A PR as `list_prs` sees it. The details are unknown for PRs that were
+/// skipped before they were looked at closely, e.g. drafts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PrListing {
+    pub repo: String,
+    pub number: String,
+    pub dependency: Option<String>,
+    /// The version bumped to, unknown for grouped updates.
+    pub version: Option<String>,
+    pub rebased: Option<bool>,
+    pub checks_pass: Option<bool>,
+    /// `eligible` or `skip: <reason>`.
+    pub status: String,
+    /// A run would still merge the PR, or rebase it to merge it later.
+    pub pending: bool,
+}
+
+/// Lists the PRs of every repo with what a run would make of them, without
+/// changing anything. Repos that fail are logged and returned by name, in
+/// place of their PRs.
+pub async fn list_prs(octo: &Octocrab, cfg: &Config) -> (Vec<PrListing>, Vec<String>) {
+    let github = &GitHub::new(octo, &cfg.retry);
+    let (repo_configs, failed_owners) = expand_repos(github, cfg).await;
+    let listed: Vec<Result<Vec<PrListing>, String>> = stream::iter(repo_configs.iter())
+        .map(|repo| async move {
+            let settings = cfg.repo_settings(repo);
+            if settings.paused {
+                info!("[{}] skipped (paused)", repo.name());
+                return Ok(Vec::new());
+            }
+            let mut report = RepoReport::new(repo.name());
+            let prs = match github.dependabot_prs(cfg, &settings, &mut report).await {
+                Ok(prs) => prs,
+                Err(e) => {
+                    error!("[{}] Error: {:?}", repo.name(), e);
+                    return Err(repo.name().to_string());
+                }
+            };
+            eligible_prs(cfg, &settings, &prs, &mut report);
+            Ok(decisions(&prs, &report)
+                .iter()
+                .map(|decision| {
+                    let pr = prs.iter().find(|pr| pr.url == decision.url);
+                    PrListing {
+                        repo: repo.name().to_string(),
+                        number: decision.number().to_string(),
+                        dependency: pr.map(|pr| {
+                            pr.updates()
+                                .iter()
+                                .map(|update| update.name.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        }),
+                        version: pr
+                            .filter(|pr| !pr.is_group)
+                            .map(|pr| pr.new_version.clone()),
+                        rebased: pr.map(|pr| !pr.needs_rebase()),
+                        checks_pass: pr.map(|pr| pr.all_checks_pass),
+                        status: match &decision.reason {
+                            Some(reason) => format!("{}: {}", decision.verdict, reason),
+                            None => decision.verdict.to_string(),
+                        },
+                        pending: decision.verdict == "eligible"
+                            || decision.reason.as_deref() == Some("not rebased"),
+                    }
+                })
+                .collect())
+        })
+        .buffered(cfg.concurrency.max(1))
+        .collect()
+        .await;
+    let mut listings = Vec::new();
+    let mut failed: Vec<String> = failed_owners.into_iter().map(|repo| repo.repo).collect();
+    for result in listed {
+        match result {
+            Ok(mut repo_listings) => listings.append(&mut repo_listings),
+            Err(repo) => failed.push(repo),
+        }
+    }
+    (listings, failed)
+}
+
+/// Outcome of one of the `doctor` checks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthCheck {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl HealthCheck {
+    fn new(name: impl Into<String>, result: Result<String, String>) -> Self {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        Self {
+            name: name.into(),
+            ok,
+            detail,
+        }
+    }
+}
+
+/// Checks that the API is reachable and that the token may read and merge
+/// the PRs of every repo, without changing anything.
+pub async fn doctor(octo: &Octocrab, cfg: &Config) -> Vec<HealthCheck> {
+    let retry = &cfg.retry;
+    let mut checks = Vec::new();
+    let api_url = cfg.api_url.as_deref().unwrap_or("https://api.github.com");
+    let resp = send_with_retry_raw(retry, true, || octo._get("/rate_limit")).await;
+    let resp = match resp {
+        Ok(resp) => resp,
+        Err(e) => {
+            checks.push(HealthCheck::new(
+                "API connectivity",
+                Err(format!("{}: {}", api_url, e)),
+            ));
+            return checks;
+        }
+    };
+    checks.push(HealthCheck::new(
+        "API connectivity",
+        Ok(api_url.to_string()),
+    ));
+    // Only classic personal tokens have scopes, the others are limited
+    // by their permissions, which the repo checks cover.
+    let scopes = resp
+        .headers()
+        .get("x-oauth-scopes")
+        .and_then(|scopes| scopes.to_str().ok())
+        .map(|scopes| scopes.split(',').map(str::trim).collect::<Vec<_>>());
+    checks.push(HealthCheck::new(
+        "token scopes",
+        match scopes {
+            Some(scopes) if scopes.contains(&"repo") => Ok(scopes.join(", ")),
+            Some(scopes) => Err(format!("missing `repo`, got {:?}", scopes.join(", "))),
+            None => Ok("no scopes, not a classic token".to_string()),
+        },
+    ));
+
+    let github = GitHub::new(octo, retry);
+    let (repo_configs, failed) = expand_repos(&github, cfg).await;
+    for report in failed {
+        checks.push(HealthCheck::new(
+            format!("{}: list repos", report.repo),
+            Err(report.error.unwrap_or_default()),
+        ));
+    }
+    for repo in repo_configs.iter() {
+        let Some((org, name)) = parse_repo_name(repo.name()) else {
+            continue;
+        };
+        let url = format!("/repos/{}/{}/pulls?state=open&per_page=1", org, name);
+        let prs: octocrab::Result<Vec<serde_json::Value>> =
+            send_with_retry(retry, true, || octo._get(url.as_str())).await;
+        checks.push(HealthCheck::new(
+            format!("{}: read PRs", repo.name()),
+            prs.map(|_| "ok".to_string()).map_err(|e| e.to_string()),
+        ));
+
+        let url = format!("/repos/{}/{}", org, name);
+        let info: octocrab::Result<RepoInfo> =
+            send_with_retry(retry, true, || octo._get(url.as_str())).await;
+        checks.push(HealthCheck::new(
+            format!("{}: merge PRs", repo.name()),
+            match info.as_ref().map(RepoInfo::can_merge) {
+                Ok(Some(true)) => Ok("ok".to_string()),
+                // Such repos are never merged to.
+                Ok(Some(false)) if cfg.repo_settings(repo).action == RepoAction::ApproveOnly => {
+                    Ok("no write permission, approve_only".to_string())
+                }
+                Ok(Some(false)) => Err("no write permission".to_string()),
+                Ok(None) => Ok("permissions not reported".to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+        ));
+    }
+    checks
+}
+
+/// Checks the config for every problem at once, so they can all be fixed
+/// in one go. Returns the credentials to authenticate with.
+pub fn validate_config(cfg: &Config, env_token: Option<String>) -> Result<Credentials, String> {
+    let mut problems = Vec::new();
+    if cfg.repos.is_empty() {
+        problems.push("`repos` is empty".to_string());
+    }
+    for repo in cfg.repos.iter() {
+        match parse_repo_name(repo.name()) {
+            None => problems.push(format!(
+                "repo {:?} is not in the `owner/name` format",
+                repo.name()
+            )),
+            Some((owner, _)) if owner.contains('*') => problems.push(format!(
+                "repo {:?} can only use `*` in the repo name",
+                repo.name()
+            )),
+            Some(_) => {}
+        }
+    }
+    if let Some(url) = cfg.api_url.as_ref().filter(|url| !is_http_url(url)) {
+        problems.push(format!("api_url {:?} is not an http(s) URL", url));
+    }
+    if let Some(url) = cfg.notify_webhook.as_ref().filter(|url| !is_http_url(url)) {
+        problems.push(format!("notify_webhook {:?} is not an http(s) URL", url));
+    }
+    if cfg.request_timeout_secs == 0 {
+        problems.push("request_timeout_secs must be positive".to_string());
+    }
+    if cfg.close_stale_after_days.is_some_and(|days| days <= 0) {
+        problems.push("close_stale_after_days must be positive".to_string());
+    }
+    if let Some(re) = cfg
+        .version_regex
+        .as_ref()
+        .filter(|re| re.captures_len() < 2)
+    {
+        problems.push(format!(
+            "version_regex {:?} has no capture group for the new version",
+            re.as_str()
+        ));
+    }
+    if cfg.merge_via_comment && (cfg.use_merge_queue || cfg.enable_auto_merge) {
+        problems.push(
+            "merge_via_comment leaves merging to dependabot, it can't be combined with use_merge_queue or enable_auto_merge"
+                .to_string(),
+        );
+    }
+    if cfg.quarantine_hours.is_some() && cfg.state_file.is_none() {
+        problems.push("quarantine_hours needs a state_file to remember merges in".to_string());
+    }
+    if !cfg.rebase_command.trim().starts_with("@dependabot ") {
+        problems.push(format!(
+            "rebase_command {:?} is not a `@dependabot` command",
+            cfg.rebase_command
+        ));
+    }
+    for (name, ceiling) in cfg.version_ceilings.iter() {
+        if numeric_version(ceiling).is_none() {
+            problems.push(format!(
+                "version_ceilings.{} {:?} is not a version",
+                name, ceiling
+            ));
+        }
+    }
+    if let Some(window) = &cfg.merge_window {
+        if window.start_hour > 23 || window.end_hour > 24 {
+            problems.push(format!(
+                "merge_window hours {}-{} are not within 0-24",
+                window.start_hour, window.end_hour
+            ));
+        }
+    }
+    if let Some(addr) = cfg
+        .metrics_addr
+        .as_ref()
+        .filter(|addr| addr.parse::<SocketAddr>().is_err())
+    {
+        problems.push(format!(
+            "metrics_addr {:?} is not an `ip:port` address",
+            addr
+        ));
+    }
+    let credentials = resolve_credentials(cfg, env_token);
+    if let Err(e) = &credentials {
+        problems.push(e.clone());
+    }
+
+    if problems.is_empty() {
+        credentials
+    } else {
+        Err(format!("Invalid config:\n  - {}", problems.join("\n  - ")))
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.parse::<http::Uri>()
+        .is_ok_and(|uri| matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some())
+}
+
+/// Splits `owner/name` into its parts.
+fn parse_repo_name(name: &str) -> Option<(&str, &str)> {
+    let (owner, repo) = name.split_once('/')?;
+    let valid = |part: &str| !part.is_empty() && !part.contains(['/', ' ']);
+    (valid(owner) && valid(repo)).then_some((owner, repo))
+}
+
+/// Exactly one of `github_token` and `[auth]` may be set in the config.
+/// `GITHUB_TOKEN` is only consulted when neither is.
+fn resolve_credentials(cfg: &Config, env_token: Option<String>) -> Result<Credentials, String> {
+    let mut cfg_token = cfg.github_token.clone().filter(|t| !t.is_empty());
+    if let Some(path) = &cfg.github_token_file {
+        if cfg_token.is_some() {
+            return Err(
+                "Both `github_token` and `github_token_file` are configured, only one is allowed"
+                    .to_string(),
+            );
+        }
+        let token = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read github_token_file {}: {}", path, e))?;
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(format!("github_token_file {} is empty", path));
+        }
+        cfg_token = Some(token.to_string());
+    }
+    match (&cfg.auth, cfg_token) {
+        (Some(_), Some(_)) => Err(
+            "Both `github_token` and `[auth]` are configured, only one auth method is allowed"
+                .to_string(),
+        ),
+        (Some(app), None) => Ok(Credentials::App {
+            app_id: app.app_id,
+            installation_id: app.installation_id,
+            private_key_path: app.private_key_path.clone(),
+        }),
+        (None, cfg_token) => resolve_token(cfg_token, env_token)
+            .map(Credentials::Token)
+            .ok_or(
+                "GitHub token not found: set `github_token`, `github_token_file` or `[auth]` in the config or the GITHUB_TOKEN environment variable"
+                    .to_string(),
+            ),
+    }
+}
+
+pub fn build_octocrab(
+    credentials: &Credentials,
+    api_url: Option<&str>,
+    timeout: Duration,
+) -> Result<Octocrab, Box<dyn Error>> {
+    // A hung connection would stall the whole run otherwise.
+    let mut builder = Octocrab::builder()
+        .set_connect_timeout(Some(timeout))
+        .set_read_timeout(Some(timeout))
+        .set_write_timeout(Some(timeout));
+    if let Some(api_url) = api_url {
+        builder = builder.base_uri(api_url)?;
+    }
+    // Retries are handled by `send_with_retry`, which backs off and knows
+    // which requests are safe to repeat.
+    builder.add_retry_config(octocrab::service::middleware::retry::RetryConfig::None);
+
+    match credentials {
+        Credentials::Token(token) => Ok(builder.personal_token(token.clone()).build()?),
+        Credentials::App {
+            app_id,
+            installation_id,
+            private_key_path,
+        } => {
+            let pem = fs::read(private_key_path).map_err(|e| {
+                format!(
+                    "Failed to read GitHub App private key {}: {}",
+                    private_key_path, e
+                )
+            })?;
+            let key = jsonwebtoken::EncodingKey::from_rsa_pem(&pem)?;
+            let app = builder.app((*app_id).into(), key).build()?;
+            // Installation tokens are requested and refreshed by octocrab.
+            Ok(app.installation((*installation_id).into()))
+        }
+    }
+}
+
+/// Picks the token from the config file, falling back to the environment.
+/// Empty values are treated as missing.
+fn resolve_token(cfg_token: Option<String>, env_token: Option<String>) -> Option<String> {
+    cfg_token
+        .filter(|t| !t.is_empty())
+        .or(env_token.filter(|t| !t.is_empty()))
+}
+
+pub async fn check_prs<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    settings: &RepoSettings,
+    report: &mut RepoReport,
+) -> Result<(), AutomergeError> {
+    let cfg = ctx.cfg;
+    let repo = &settings.name;
+    if settings.paused {
+        info!("[{}] skipped (paused)", repo);
+        report.paused = true;
+        return Ok(());
+    }
+    if let Some(hours) = cfg.quarantine_hours {
+        check_merges(ctx, repo, chrono::Duration::hours(hours)).await;
+    }
+    let prs = ctx.github.dependabot_prs(cfg, settings, report).await?;
+    ctx.state(|state| state.retain_open(repo, &prs));
+    let approve_only;
+    let settings = if report.read_only {
+        approve_only = RepoSettings {
+            action: RepoAction::ApproveOnly,
+            ..settings.clone()
+        };
+        &approve_only
+    } else {
+        settings
+    };
+    let result = act_on_prs(ctx, settings, &prs, report).await;
+    // The fields are for `--log-format json`.
+    for decision in decisions(&prs, report) {
+        info!(
+            repo = repo.as_str(),
+            pr = decision.number(),
+            action = decision.verdict,
+            reason = decision.reason.as_deref();
+            "[{}] {}", repo, decision
+        );
+    }
+    result
+}
+
+/// Merges are given this long to be checked before they're forgotten.
+const MERGE_CHECKS_TIMEOUT_HOURS: i64 = 24;
+
+/// Quarantines the dependencies of earlier merges in `repo` whose checks
+/// failed. Failures to look are retried in the next run.
+async fn check_merges<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    repo: &str,
+    quarantine: chrono::Duration,
+) {
+    let now = Utc::now();
+    let merges = ctx
+        .state(|state| {
+            state.expire_quarantines(now);
+            state.pending_merges(repo)
+        })
+        .unwrap_or_default();
+    for merge in merges {
+        let passed = match ctx
+            .github
+            .commit_checks_pass(repo, &merge.sha, &ctx.cfg.blocking_conclusions)
+            .await
+        {
+            Ok(passed) => passed,
+            Err(e) => {
+                warn!("[{}] Failed to check merge {}: {}", repo, merge.sha, e);
+                continue;
+            }
+        };
+        let quarantine_until = match passed {
+            Some(true) => None,
+            Some(false) => {
+                warn!(
+                    "[{}] Checks of merge {} failed, quarantining {} for {} hours",
+                    repo,
+                    merge.sha,
+                    merge.dependencies.join(", "),
+                    quarantine.num_hours()
+                );
+                Some(now + quarantine)
+            }
+            None if now - merge.at > chrono::Duration::hours(MERGE_CHECKS_TIMEOUT_HOURS) => None,
+            None => continue,
+        };
+        ctx.state(|state| state.resolve_merge(&merge.sha, quarantine_until));
+    }
+}
+
+/// The first of the dependencies of the PR that's quarantined, and until when.
+fn quarantined<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    pr: &DependabotPr,
+) -> Option<(String, DateTime<Utc>)> {
+    let repo = format!("{}/{}", pr.repo.org, pr.repo.repo);
+    let now = Utc::now();
+    ctx.state(|state| {
+        pr.updates().into_iter().find_map(|update| {
+            let until = state.quarantined_until(&repo, &update.name, now)?;
+            Some((update.name, until))
+        })
+    })
+    .flatten()
+}
+
+/// Closes the PRs older than `days` that can't be merged because their
+/// checks fail or their conflicts need manual resolution. Those the config
+/// holds back stay open. Returns the URLs of the closed PRs.
+async fn close_stale<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    settings: &RepoSettings,
+    prs: &[DependabotPr],
+    days: i64,
+    report: &mut RepoReport,
+) -> Result<Vec<String>, AutomergeError> {
+    let cfg = ctx.cfg;
+    let now = Utc::now();
+    let mut closed = Vec::new();
+    for pr in prs {
+        if !is_old_enough(pr.created_at, chrono::Duration::days(days), now)
+            || hold_label(pr, cfg).is_some()
+            || superseded_by(pr, prs).is_some()
+            || policy_skip_reason(pr, cfg, settings).is_some()
+        {
+            continue;
+        }
+        let reason = if !pr.all_checks_pass {
+            "checks failing"
+        } else if rebase_command(pr, cfg).is_none() && pr.has_conflicts() {
+            "needs manual conflict resolution"
+        } else {
+            continue;
+        };
+        let body = format!(
+            "Closed by automerge: open for more than {} days and can't be merged ({}).",
+            days, reason
+        );
+        if ctx.dry_run {
+            info!("Would close {:?}: {}", pr.url, reason);
+        } else {
+            info!("Closing {:?}: {}", pr.url, reason);
+            ctx.github.comment(pr, &body).await?;
+            ctx.github.close(pr).await?;
+            if let Some(notifier) = ctx.notifier {
+                notifier.notify(pr, Action::Closed, None).await;
+            }
+        }
+        report.record(&pr.url, Action::Closed, Some(reason.to_string()));
+        closed.push(pr.url.clone());
+    }
+    Ok(closed)
+}
+
+/// Merges one of the PRs and requests a rebase of another.
+async fn act_on_prs<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    settings: &RepoSettings,
+    prs: &[DependabotPr],
+    report: &mut RepoReport,
+) -> Result<(), AutomergeError> {
+    let cfg = ctx.cfg;
+    let repo = &settings.name;
+    if prs.is_empty() {
+        if report.prs_found == 0 {
+            info!("[{}] No dependabot PRs found", repo);
+        } else {
+            info!(
+                "[{}] No dependabot PRs to merge: {} found, {} skipped",
+                repo,
+                report.prs_found,
+                report.skipped_prs()
+            );
+        }
+        return Ok(());
+    }
+
+    if prs.iter().any(|pr| pr.rebase_in_progress) {
+        info!(
+            "[{}] One of the PRs is being rebased already. Skipping further actions.",
+            repo
+        );
+        for pr in prs.iter() {
+            report.record(
+                &pr.url,
+                Action::Skipped,
+                Some("another PR is being rebased".to_string()),
+            );
+        }
+        return Ok(());
+    }
+
+    let held_from = report.events.len();
+    let mut eligible = eligible_prs(cfg, settings, prs, report);
+    let mut held = report.events[held_from..].to_vec();
+    // Closing takes write permission too.
+    if let Some(days) = cfg.close_stale_after_days.filter(|_| !report.read_only) {
+        let closed = close_stale(ctx, settings, prs, days, report).await?;
+        eligible.retain(|pr| !closed.contains(&pr.url));
+        held.retain(|event| !closed.contains(&event.url));
+    }
+    for event in held {
+        let reason = event.reason.as_deref().unwrap_or_default();
+        let explain = cfg.comment_skip_reasons
+            || (cfg.unparsed_versions == UnparsedVersionPolicy::RequireManual
+                && reason == UNPARSED_VERSION_REASON);
+        if !explain {
+            continue;
+        }
+        if let Some(pr) = prs.iter().find(|pr| pr.url == event.url) {
+            explain_skip(ctx, pr, reason).await;
+        }
+    }
+    let mut prs = eligible;
+    prs.retain(|pr| match quarantined(ctx, pr) {
+        Some((dependency, until)) => {
+            report.skip(
+                &pr.url,
+                format!("{} is quarantined until {}", dependency, until.to_rfc3339()),
+            );
+            false
+        }
+        None => true,
+    });
+
+    let window = cfg.merge_window.as_ref();
+    let in_window = match window {
+        Some(window) => window.contains(Utc::now()),
+        None => true,
+    };
+    let merged = if in_window {
+        match settings.action {
+            RepoAction::Merge => maybe_merge_one(ctx, &prs, settings.merge_method, report).await?,
+            RepoAction::ApproveOnly => {
+                approve_ready(ctx, &prs, report).await?;
+                None
+            }
+            RepoAction::RebaseOnly => {
+                for pr in prs.iter().filter(|pr| pr.ready_to_merge()) {
+                    report.skip(&pr.url, "action is rebase_only");
+                }
+                None
+            }
+        }
+    } else {
+        info!("[{}] Outside merge_window, not merging", repo);
+        for pr in prs.iter().filter(|pr| pr.ready_to_merge()) {
+            report.skip(&pr.url, "outside merge_window");
+        }
+        if window.is_some_and(|window| !window.rebase_outside) {
+            return Ok(());
+        }
+        None
+    };
+    let maybe_rebase = if let Some(merged) = merged {
+        prs.iter()
+            .find(|pr| pr.url != merged.url && pr.needs_rebase())
+    } else {
+        prs.iter().find(|pr| pr.needs_rebase())
+    };
+
+    if let Some(to_rebase) = maybe_rebase {
+        let since = Utc::now() - chrono::Duration::minutes(cfg.rebase_comment_lookback_minutes);
+        let requests = ctx
+            .state(|state| state.rebase_requests(to_rebase))
+            .unwrap_or(0);
+        if requests >= STUCK_AFTER_REBASE_REQUESTS {
+            let reason = format!("dependabot didn't rebase it after {} requests", requests);
+            warn!(
+                "{:?}: {}, manual intervention needed",
+                to_rebase.url, reason
+            );
+            if ctx.state(|state| state.escalate(to_rebase)) == Some(true) {
+                if let Some(notifier) = ctx.notifier.filter(|_| !ctx.dry_run) {
+                    notifier
+                        .notify(to_rebase, Action::Skipped, Some(&reason))
+                        .await;
+                }
+            }
+            report.skip(&to_rebase.url, reason);
+            return Ok(());
+        }
+        // Saves looking through the comments.
+        if ctx.state(|state| state.rebase_requested_since(to_rebase, since)) == Some(true) {
+            report.skip(
+                &to_rebase.url,
+                "rebase already requested, waiting for dependabot",
+            );
+            return Ok(());
+        }
+        let comments = ctx.github.comments_since(to_rebase, since).await?;
+        let commands = [cfg.rebase_command.trim(), RECREATE_COMMAND];
+        if has_comment(&comments, since, &commands) {
+            report.skip(
+                &to_rebase.url,
+                "rebase already requested, waiting for dependabot",
+            );
+            return Ok(());
+        }
+
+        if ctx.dry_run {
+            info!("Would rebase {:?}", to_rebase.url);
+            report.record(&to_rebase.url, Action::Rebased, None);
+            return Ok(());
+        }
+
+        // PRs dependabot can't rebase were filtered out above.
+        let command = rebase_command(to_rebase, cfg).unwrap_or(&cfg.rebase_command);
+        info!("Rebasing {:?} with {:?}", to_rebase.url, command);
+        if let Err(e) = post_command(ctx, to_rebase, command).await {
+            if !is_secondary_rate_limit(&e) {
+                return Err(e.into());
+            }
+            warn!(
+                "Still secondary rate limited, not rebasing {:?}",
+                to_rebase.url
+            );
+            report.skip(&to_rebase.url, "secondary rate limited");
+            return Ok(());
+        }
+        report.record(&to_rebase.url, Action::Rebased, None);
+        ctx.remember(to_rebase, Action::Rebased);
+
+        if cfg.wait_for_rebase && in_window && settings.action == RepoAction::Merge {
+            wait_and_merge(ctx, settings, to_rebase, report).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops the PRs that mustn't be merged, recording why, and puts the rest
+/// in merge order.
+fn eligible_prs(
+    cfg: &Config,
+    settings: &RepoSettings,
+    prs: &[DependabotPr],
+    report: &mut RepoReport,
+) -> Vec<DependabotPr> {
+    // Merging those would only waste a CI run on the base branch.
+    let prs = prs
+        .iter()
+        .filter(|pr| match superseded_by(pr, prs) {
+            Some(newer) => {
+                info!("{:?} is superseded by {:?}", pr.url, newer.url);
+                report.skip(&pr.url, format!("superseded by {}", newer.url));
+                false
+            }
+            None => true,
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut prs = prs
+        .into_iter()
+        .filter(|pr| {
+            if let Some(label) = hold_label(pr, cfg) {
+                report.skip(&pr.url, format!("on hold ({})", label));
+                return false;
+            }
+            if rebase_command(pr, cfg).is_none() && pr.has_conflicts() {
+                warn!(
+                    "Dependabot couldn't resolve the conflicts of {:?}, manual conflict resolution needed",
+                    pr.url
+                );
+                report.skip(&pr.url, "needs manual conflict resolution");
+                return false;
+            }
+            if rebase_command(pr, cfg).is_none() {
+                warn!(
+                    "Dependabot couldn't rebase {:?}, manual intervention needed",
+                    pr.url
+                );
+                report.skip(&pr.url, "dependabot couldn't rebase");
+                return false;
+            }
+            if let Some(reason) = policy_skip_reason(pr, cfg, settings) {
+                report.skip(&pr.url, reason);
+                return false;
+            }
+            true
+        })
+        .collect::<Vec<_>>();
+    if let Some(order) = cfg.merge_order {
+        sort_prs(&mut prs, order);
+    }
+    if cfg.security_updates_first {
+        prs.sort_by_key(|pr| !pr.is_security);
+    }
+    prs
+}
+
+/// Why the config rules the PR out, e.g. its labels or bump size, as
+/// opposed to the PR being unmergeable.
+fn policy_skip_reason(pr: &DependabotPr, cfg: &Config, settings: &RepoSettings) -> Option<String> {
+    if let Some(reason) = label_skip_reason(
+        &pr.labels,
+        &settings.require_labels,
+        &settings.exclude_labels,
+    ) {
+        return Some(reason);
+    }
+    let updates = pr.updates();
+    if cfg.unparsed_versions != UnparsedVersionPolicy::Merge
+        && updates
+            .iter()
+            .any(|update| version_kind(&update.new_version).is_none())
+    {
+        return Some(UNPARSED_VERSION_REASON.to_string());
+    }
+    if !cfg.allow_prereleases
+        && updates
+            .iter()
+            .any(|update| prerelease_of(&update.new_version).is_some())
+    {
+        return Some("pre-release version".to_string());
+    }
+    if let Some(reason) = ceiling_skip_reason(&updates, &cfg.version_ceilings) {
+        return Some(reason);
+    }
+    // The biggest bump in a group decides.
+    let max_bump = settings.max_bump?;
+    match pr.bump() {
+        Some(bump) if bump <= max_bump => None,
+        Some(bump) => Some(format!("{:?} bump", bump)),
+        None => Some("bump size is unknown".to_string()),
+    }
+}
+
+const UNPARSED_VERSION_REASON: &str = "new version couldn't be parsed";
+
+fn hold_label<'a>(pr: &'a DependabotPr, cfg: &Config) -> Option<&'a str> {
+    pr.labels
+        .iter()
+        .find(|label| cfg.hold_labels.contains(label))
+        .map(String::as_str)
+}
+
+/// Why one of the updates goes beyond its `version_ceilings` entry.
+/// Versions that can't be compared to the ceiling are held back too.
+fn ceiling_skip_reason(
+    updates: &[DependencyUpdate],
+    ceilings: &HashMap<String, String>,
+) -> Option<String> {
+    updates.iter().find_map(|update| {
+        let ceiling = ceilings.get(&update.name)?;
+        match compare_versions(&update.new_version, ceiling) {
+            Some(std::cmp::Ordering::Greater) => Some(format!(
+                "{} {} is above the {} ceiling",
+                update.name, update.new_version, ceiling
+            )),
+            Some(_) => None,
+            None => Some(format!(
+                "{} {} can't be compared to the {} ceiling",
+                update.name, update.new_version, ceiling
+            )),
+        }
+    })
+}
+
+/// Stable, so equal PRs stay in the order GitHub listed them.
+fn sort_prs(prs: &mut [DependabotPr], order: MergeOrder) {
+    match order {
+        MergeOrder::OldestFirst => prs.sort_by_key(|pr| (pr.created_at.is_none(), pr.created_at)),
+        MergeOrder::NewestFirst => prs.sort_by_key(|pr| std::cmp::Reverse(pr.created_at)),
+        MergeOrder::SmallestBumpFirst => prs.sort_by_key(|pr| {
+            let bump = pr.bump();
+            (bump.is_none(), bump)
+        }),
+    }
+}
+
+/// What was done to a PR in a run, or why it was left alone.
+#[derive(Debug, Clone)]
+struct Decision<'a> {
+    url: &'a str,
+    /// `skip`, `eligible` or an `Action`.
+    verdict: &'static str,
+    reason: Option<String>,
+}
+
+impl Decision<'_> {
+    fn number(&self) -> &str {
+        self.url.rsplit('/').next().unwrap_or(self.url)
+    }
+}
+
+/// E.g. `skip #123: checks failing`.
+impl std::fmt::Display for Decision<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} #{}", self.verdict, self.number())?;
+        if let Some(reason) = &self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// A decision per PR that was looked at, in the order they were reported.
+fn decisions<'a>(prs: &'a [DependabotPr], report: &'a RepoReport) -> Vec<Decision<'a>> {
+    // PRs skipped while fetching are in the report only.
+    let mut urls = Vec::<&str>::new();
+    for url in report
+        .events
+        .iter()
+        .map(|event| event.url.as_str())
+        .chain(prs.iter().map(|pr| pr.url.as_str()))
+    {
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls.into_iter()
+        .map(|url| {
+            let (verdict, reason) = match report.events.iter().rev().find(|event| event.url == url)
+            {
+                Some(PrEvent {
+                    action: Action::Skipped,
+                    reason,
+                    ..
+                }) => (
+                    "skip",
+                    Some(reason.clone().unwrap_or_else(|| "unknown".to_string())),
+                ),
+                Some(event) => (event.action.as_str(), None),
+                None => match prs
+                    .iter()
+                    .find(|pr| pr.url == url)
+                    .and_then(|pr| pr.skip_reason())
+                {
+                    Some(reason) => ("skip", Some(reason.to_string())),
+                    None => ("eligible", None),
+                },
+            };
+            Decision {
+                url,
+                verdict,
+                reason,
+            }
+        })
+        .collect()
+}
+
+/// Comments `command`. Rate limits with a `Retry-After` are retried by
+/// `GitHub::comment`, this retries once after `SECONDARY_RATE_LIMIT_WAIT`
+/// when GitHub's secondary rate limit rejects the comment without one.
+async fn post_command<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    pr: &DependabotPr,
+    command: &str,
+) -> octocrab::Result<()> {
+    match ctx.github.comment(pr, command).await {
+        Err(e) if is_secondary_rate_limit(&e) => {
+            warn!(
+                "Secondary rate limited commenting on {:?}, retrying in {}s",
+                pr.url,
+                SECONDARY_RATE_LIMIT_WAIT.as_secs()
+            );
+            tokio::time::sleep(SECONDARY_RATE_LIMIT_WAIT).await;
+            ctx.github.comment(pr, command).await
+        }
+        res => res,
+    }
+}
+
+/// Whether GitHub rejected a request for creating content too quickly.
+/// These 403s don't always carry a `Retry-After` header for
+/// `send_with_retry` to act on.
+fn is_secondary_rate_limit(e: &octocrab::Error) -> bool {
+    match e {
+        octocrab::Error::GitHub { source, .. } => {
+            source.status_code == StatusCode::FORBIDDEN
+                && source
+                    .message
+                    .to_lowercase()
+                    .contains("secondary rate limit")
+        }
+        _ => false,
+    }
+}
+
+/// Comments why the PR isn't merged, unless that was commented already.
+/// Failures are only logged, the comment is a courtesy.
+async fn explain_skip<G: GitHubApi>(ctx: &RunContext<'_, G>, pr: &DependabotPr, reason: &str) {
+    let body = format!("Not auto-merged: {}.", reason);
+    let since = pr.created_at.unwrap_or(DateTime::UNIX_EPOCH);
+    match ctx.github.comments_since(pr, since).await {
+        Ok(comments) if has_comment(&comments, since, &[body.as_str()]) => return,
+        Ok(_) => {}
+        Err(e) => {
+            warn!("Failed to read the comments of {:?}: {}", pr.url, e);
+            return;
+        }
+    }
+    if ctx.dry_run {
+        info!("Would comment {:?} on {:?}", body, pr.url);
+        return;
+    }
+    if let Err(e) = ctx.github.comment(pr, &body).await {
+        warn!("Failed to comment on {:?}: {}", pr.url, e);
+    }
+}
+
+/// Polls a PR that dependabot was asked to rebase and merges it as soon
+/// as it's rebased and its checks pass, giving up after
+/// `wait_for_rebase_timeout_secs`.
+async fn wait_and_merge<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    settings: &RepoSettings,
+    pr: &DependabotPr,
+    report: &mut RepoReport,
+) -> Result<(), AutomergeError> {
+    let deadline =
+        tokio::time::Instant::now() + Duration::from_secs(ctx.cfg.wait_for_rebase_timeout_secs);
+    loop {
+        // Decisions about the other PRs are already in `report`.
+        let mut scratch = RepoReport::new(&settings.name);
+        let refreshed = ctx
+            .github
+            .dependabot_prs(ctx.cfg, settings, &mut scratch)
+            .await?
+            .into_iter()
+            .find(|p| p.number == pr.number);
+        let Some(refreshed) = refreshed else {
+            info!("{:?} is gone, stopped waiting for its rebase", pr.url);
+            return Ok(());
+        };
+
+        if refreshed.ready_to_merge() && !refreshed.rebase_in_progress {
+            maybe_merge_one(ctx, &[refreshed], settings.merge_method, report).await?;
+            return Ok(());
+        }
+        if tokio::time::Instant::now() + REBASE_POLL_INTERVAL > deadline {
+            info!("Timed out waiting for {:?} to be rebased", pr.url);
+            return Ok(());
+        }
+        tokio::time::sleep(REBASE_POLL_INTERVAL).await;
+    }
+}
+
+pub async fn maybe_merge_one<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    prs: &[DependabotPr],
+    merge_method: Option<MergeMethod>,
+    report: &mut RepoReport,
+) -> Result<Option<DependabotPr>, AutomergeError> {
+    let cfg = ctx.cfg;
+    for pr in prs.iter().filter(|pr| pr.has_conflicts()) {
+        report.skip(&pr.url, "merge conflicts");
+    }
+
+    if let Some(pr) = prs.iter().find(|pr| pr.ready_to_merge()) {
+        if cfg.merge_via_comment {
+            return merge_via_comment(ctx, pr, merge_method, report).await;
+        }
+        let mut enqueue = false;
+        if cfg.use_merge_queue || cfg.enable_auto_merge {
+            let queue = ctx.github.merge_queue_state(pr).await?;
+            // Merging directly would conflict with what GitHub is doing.
+            if queue.is_in_merge_queue || queue.auto_merge_request.is_some() {
+                report.skip(&pr.url, "already queued for merging");
+                return Ok(None);
+            }
+            enqueue = cfg.use_merge_queue && queue.is_merge_queue_enabled;
+        }
+        let auto_merge = cfg.enable_auto_merge && !enqueue;
+
+        if !ctx.take_merge() {
+            report.skip(&pr.url, "max_merges_per_run reached");
+            return Ok(None);
+        }
+
+        let blocked = pr.mergeable_state == Some(MergeableState::Blocked);
+        if ctx.dry_run {
+            if cfg.auto_approve {
+                info!("Would approve {:?}", pr.url);
+                report.record(&pr.url, Action::Approved, None);
+            }
+            if auto_merge {
+                info!("Would enable auto-merge for {:?}", pr.url);
+                report.record(&pr.url, Action::AutoMerge, None);
+                return Ok(Some(pr.clone()));
+            }
+            if blocked {
+                ctx.return_merge();
+                report.skip(&pr.url, "blocked by branch protection");
+                return Ok(None);
+            }
+            if enqueue {
+                info!("Would add {:?} to the merge queue", pr.url);
+                report.record(&pr.url, Action::Queued, None);
+                return Ok(Some(pr.clone()));
+            }
+            info!("Would merge {:?}", pr.url);
+            report.record(&pr.url, Action::Merged, None);
+            if cfg.delete_branch_after_merge {
+                info!("Would delete branch {}", pr.head_ref);
+            }
+            return Ok(Some(pr.clone()));
+        }
+
+        info!("Merging {:?}", pr.url);
+
+        if cfg.auto_approve {
+            if let Err(e) = approve(ctx, pr, report).await {
+                ctx.return_merge();
+                return Err(e);
+            }
+        }
+
+        // GitHub waits for branch protection itself, blocked or not.
+        if auto_merge {
+            match ctx.github.enable_auto_merge(pr, merge_method).await {
+                Ok(()) => {
+                    info!("Enabled auto-merge for {:?}", pr.url);
+                    report.record(&pr.url, Action::AutoMerge, None);
+                    ctx.remember(pr, Action::AutoMerge);
+                    if let Some(notifier) = ctx.notifier {
+                        notifier.notify(pr, Action::AutoMerge, None).await;
+                    }
+                    return Ok(Some(pr.clone()));
+                }
+                Err(e) => warn!(
+                    "Failed to enable auto-merge for {:?}, merging directly: {}",
+                    pr.url, e
+                ),
+            }
+        }
+
+        // Our approval may be the missing requirement, so approve anyway
+        // and leave the merge to a run that sees the PR unblocked.
+        if blocked {
+            ctx.return_merge();
+            report.skip(&pr.url, "blocked by branch protection");
+            return Ok(None);
+        }
+
+        if enqueue {
+            return match ctx.github.enqueue(pr).await {
+                Ok(()) => {
+                    info!("Added {:?} to the merge queue", pr.url);
+                    report.record(&pr.url, Action::Queued, None);
+                    ctx.remember(pr, Action::Queued);
+                    if let Some(notifier) = ctx.notifier {
+                        notifier.notify(pr, Action::Queued, None).await;
+                    }
+                    Ok(Some(pr.clone()))
+                }
+                Err(e) => {
+                    info!("Failed to add {:?} to the merge queue: {}", pr.url, e);
+                    ctx.return_merge();
+                    let e = e.to_string();
+                    if let Some(notifier) = ctx.notifier {
+                        notifier.notify(pr, Action::Failed, Some(&e)).await;
+                    }
+                    report.record(&pr.url, Action::Failed, Some(e));
+                    Ok(None)
+                }
+            };
+        }
+
+        let mut cooldown = ctx.merge_cooldown().await;
+        let commit = MergeCommit::new(cfg, pr);
+        let result = ctx.github.merge(pr, merge_method, &commit).await;
+        if let (Ok(_), Some(last_merge)) = (&result, cooldown.as_deref_mut()) {
+            *last_merge = Some(tokio::time::Instant::now());
+        }
+        drop(cooldown);
+        let merged = match result {
+            Ok(merged) => merged,
+            Err(e) => {
+                let e = match &e {
+                    octocrab::Error::GitHub { source, .. } => {
+                        branch_protection_reason(source.status_code, &source.message)
+                    }
+                    _ => None,
+                }
+                .unwrap_or_else(|| e.to_string());
+                info!("Failed to merge {:?}: {}", pr.url, e);
+                ctx.return_merge();
+                if let Some(notifier) = ctx.notifier {
+                    notifier.notify(pr, Action::Failed, Some(&e)).await;
+                }
+                report.record(&pr.url, Action::Failed, Some(e));
+                return Ok(None);
+            }
+        };
+        info!("Merged {:?} as {}", pr.url, merged.sha);
+        report.record(&pr.url, Action::Merged, None);
+        ctx.remember(pr, Action::Merged);
+        let pr = DependabotPr {
+            merge_sha: Some(merged.sha),
+            ..pr.clone()
+        };
+        if ctx.cfg.quarantine_hours.is_some() {
+            ctx.state(|state| state.record_merge(&pr, Utc::now()));
+        }
+        if let Some(notifier) = ctx.notifier {
+            notifier.notify(&pr, Action::Merged, None).await;
+        }
+        // Failures are logged only, the PR is merged already.
+        if cfg.delete_branch_after_merge {
+            match ctx.github.delete_branch(&pr).await {
+                Ok(()) => info!("Deleted branch {}", pr.head_ref),
+                Err(e) => error!("Failed to delete branch {}: {:?}", pr.head_ref, e),
+            }
+        }
+
+        Ok(Some(pr))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Dependabot has no command for rebase merges.
+fn merge_command(merge_method: Option<MergeMethod>) -> &'static str {
+    match merge_method {
+        Some(MergeMethod::Squash) => "@dependabot squash and merge",
+        _ => "@dependabot merge",
+    }
+}
+
+/// Asks dependabot to merge the PR once its checks pass, unless that was
+/// asked already.
+async fn merge_via_comment<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    pr: &DependabotPr,
+    merge_method: Option<MergeMethod>,
+    report: &mut RepoReport,
+) -> Result<Option<DependabotPr>, AutomergeError> {
+    let command = merge_command(merge_method);
+    let since = pr.created_at.unwrap_or(DateTime::UNIX_EPOCH);
+    let comments = ctx.github.comments_since(pr, since).await?;
+    if has_comment(&comments, since, &[command]) {
+        report.skip(&pr.url, "merge already requested, waiting for dependabot");
+        return Ok(None);
+    }
+    if !ctx.take_merge() {
+        report.skip(&pr.url, "max_merges_per_run reached");
+        return Ok(None);
+    }
+
+    if ctx.dry_run {
+        if ctx.cfg.auto_approve {
+            info!("Would approve {:?}", pr.url);
+            report.record(&pr.url, Action::Approved, None);
+        }
+        info!("Would comment {:?} on {:?}", command, pr.url);
+        report.record(&pr.url, Action::AutoMerge, None);
+        return Ok(Some(pr.clone()));
+    }
+
+    // Dependabot can't merge without the reviews branch protection requires.
+    if ctx.cfg.auto_approve {
+        if let Err(e) = approve(ctx, pr, report).await {
+            ctx.return_merge();
+            return Err(e);
+        }
+    }
+    info!("Asking dependabot to merge {:?} with {:?}", pr.url, command);
+    if let Err(e) = post_command(ctx, pr, command).await {
+        ctx.return_merge();
+        return Err(e.into());
+    }
+    report.record(&pr.url, Action::AutoMerge, None);
+    ctx.remember(pr, Action::AutoMerge);
+    if let Some(notifier) = ctx.notifier {
+        notifier.notify(pr, Action::AutoMerge, None).await;
+    }
+    Ok(Some(pr.clone()))
+}
+
+/// Title and body of a merge commit, left to GitHub where `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeCommit {
+    pub title: Option<String>,
+    pub message: Option<String>,
+}
+
+impl MergeCommit {
+    fn new(cfg: &Config, pr: &DependabotPr) -> Self {
+        let render = |template: &String| render_commit_template(template, pr);
+        Self {
+            title: cfg.merge_commit_title.as_ref().map(render),
+            message: cfg.merge_commit_message.as_ref().map(render),
+        }
+    }
+}
+
+/// Fills in `{dependency}`, `{new_version}` and `{pr_number}`.
+fn render_commit_template(template: &str, pr: &DependabotPr) -> String {
+    template
+        .replace("{dependency}", &pr.dependency_name)
+        .replace("{new_version}", &pr.new_version)
+        .replace("{pr_number}", &pr.number.to_string())
+}
+
+/// Approves every PR ready to be merged, for `RepoAction::ApproveOnly`.
+async fn approve_ready<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    prs: &[DependabotPr],
+    report: &mut RepoReport,
+) -> Result<(), AutomergeError> {
+    for pr in prs.iter().filter(|pr| pr.ready_to_merge()) {
+        if ctx.dry_run {
+            info!("Would approve {:?}", pr.url);
+            report.record(&pr.url, Action::Approved, None);
+            continue;
+        }
+        approve(ctx, pr, report).await?;
+    }
+    Ok(())
+}
+
+/// Approves the PR unless we already approved its current head.
+async fn approve<G: GitHubApi>(
+    ctx: &RunContext<'_, G>,
+    pr: &DependabotPr,
+    report: &mut RepoReport,
+) -> Result<(), AutomergeError> {
+    if let Some(viewer) = ctx.viewer().await {
+        let reviews = ctx.github.reviews(pr).await?;
+        if is_approved_by(&reviews, viewer, &pr.head_sha) {
+            info!("{:?} is approved already", pr.url);
+            return Ok(());
+        }
+    }
+
+    ctx.github.approve(pr).await?;
+    report.record(&pr.url, Action::Approved, None);
+    Ok(())
+}
+
+/// Whether `login` approved the PR at commit `head_sha`.
+fn is_approved_by(reviews: &[Review], login: &str, head_sha: &str) -> bool {
+    reviews.iter().any(|review| {
+        review.state == "APPROVED"
+            && review.commit_id.as_deref() == Some(head_sha)
+            && review.user.as_ref().is_some_and(|u| u.login == login)
+    })
+}
+
+/// Explains a merge rejection caused by unmet branch protection
+/// requirements. GitHub only tells us in the error message.
+fn branch_protection_reason(status: StatusCode, message: &str) -> Option<String> {
+    if status != StatusCode::METHOD_NOT_ALLOWED {
+        return None;
+    }
+    let lower = message.to_lowercase();
+    let requirement = if lower.contains("approving review") || lower.contains("code owner review") {
+        "required reviews"
+    } else if lower.contains("status check") {
+        "required checks"
+    } else if lower.contains("protected branch") || lower.contains("rule violation") {
+        "branch protection rules"
+    } else {
+        return None;
+    };
+    Some(format!("{} not met: {}", requirement, message))
+}
+
+/// PRs whose details are fetched at once.
+const PR_FETCH_CONCURRENCY: usize = 4;
+
+pub async fn dependabot_prs_passing_checks(
+    octo: &Octocrab,
+    cfg: &Config,
+    settings: &RepoSettings,
+    report: &mut RepoReport,
+) -> Result<Vec<DependabotPr>, AutomergeError> {
+    let retry = &cfg.retry;
+    let (org, repo) = parse_repo_name(&settings.name)
+        .map(|(org, repo)| (org.to_string(), repo.to_string()))
+        .ok_or_else(|| {
+            AutomergeError::Config(format!(
+                "invalid repo name {:?}, expected `owner/name`",
+                settings.name
+            ))
+        })?;
+
+    // Writes to these fail with 403s.
+    let url = format!("/repos/{}/{}", org, repo);
+    let info: RepoInfo = send_with_retry(retry, true, || octo._get(url.as_str())).await?;
+    if info.archived || info.disabled {
+        let state = if info.archived {
+            "archived"
+        } else {
+            "disabled"
+        };
+        info!("[{}] Skipping, the repo is {}", settings.name, state);
+        return Ok(Vec::new());
+    }
+    // Every merge would fail, approving only takes read access.
+    if info.can_merge() == Some(false) {
+        report.read_only = true;
+        if settings.action != RepoAction::ApproveOnly {
+            warn!(
+                "[{}] The token has no write permission on the repo, only approving PRs",
+                settings.name
+            );
+            report.error = Some(
+                "no write permission, only approving PRs; set `action = \"approve_only\"` if that's intended"
+                    .to_string(),
+            );
+        }
+    }
+
+    let (mut prs_state, mut reviews) = if cfg.use_graphql {
+        graphql_prs(octo, cfg, settings, &org, &repo, report).await?
+    } else {
+        let prs = rest_prs(octo, cfg, settings, &org, &repo, report).await?;
+        (prs, HashMap::new())
+    };
+
+    // Only PRs that need a rebase can have a failed one that matters.
+    for pr in prs_state.iter_mut().filter(|pr| pr.needs_rebase()) {
+        let since = pr.created_at.unwrap_or(DateTime::UNIX_EPOCH);
+        let comments = pr_comments_since(octo, pr, since, retry).await?;
+        pr.rebase_failed = rebase_failed(&comments, &cfg.bot_login);
+        pr.failed_rebases = failed_rebases(&comments, &cfg.bot_login);
+        pr.recreate_requested = has_comment(&comments, since, &[RECREATE_COMMAND]);
+    }
+
+    for pr in prs_state.iter() {
+        debug!(
+            "{:?}: all_checks_pass={} rebased={} rebase_in_progress={} mergeable_state={:?} {} {} -> {}",
+            pr.url,
+            pr.all_checks_pass,
+            pr.rebased,
+            pr.rebase_in_progress,
+            pr.mergeable_state,
+            pr.dependency_name,
+            pr.old_version,
+            pr.new_version
+        );
+    }
+
+    let now = Utc::now();
+    let min_age = chrono::Duration::hours(settings.min_age_hours);
+    prs_state.retain(|pr| {
+        if pr.draft {
+            report.skip(&pr.url, "draft");
+            return false;
+        }
+        let updates = pr.updates();
+        // Policies can't be checked without knowing what's being bumped.
+        if updates.is_empty() {
+            report.skip(&pr.url, "grouped updates couldn't be parsed");
+            return false;
+        }
+        if let Some(ignored) = updates.iter().find(|update| {
+            settings
+                .ignore_dependencies
+                .iter()
+                .any(|pattern| matches_pattern(pattern, &update.name))
+        }) {
+            report.skip(
+                &pr.url,
+                format!("{} is an ignored dependency", ignored.name),
+            );
+            return false;
+        }
+        let ecosystems = pr.ecosystems();
+        let matches_any = |patterns: &[String], name: &str| {
+            patterns
+                .iter()
+                .any(|pattern| matches_pattern(pattern, name))
+        };
+        if let Some(ignored) = ecosystems
+            .iter()
+            .find(|name| matches_any(&settings.ignore_ecosystems, name))
+        {
+            report.skip(&pr.url, format!("{} is an ignored ecosystem", ignored));
+            return false;
+        }
+        if !settings.ecosystems.is_empty()
+            && !ecosystems
+                .iter()
+                .any(|name| matches_any(&settings.ecosystems, name))
+        {
+            report.skip(&pr.url, "not one of ecosystems");
+            return false;
+        }
+        let skip_min_age = pr.is_security && cfg.security_updates_skip_min_age;
+        if !skip_min_age && !is_old_enough(pr.created_at, min_age, now) {
+            report.skip(
+                &pr.url,
+                format!("younger than {} hours", settings.min_age_hours),
+            );
+            return false;
+        }
+        true
+    });
+
+    if !cfg.allow_human_commits {
+        let mut bot_only = Vec::with_capacity(prs_state.len());
+        for pr in prs_state {
+            let commits = pr_commits(octo, &pr, retry).await?;
+            let authors = other_commit_authors(&commits, &cfg.bot_login);
+            if authors.is_empty() {
+                bot_only.push(pr);
+            } else {
+                report.skip(&pr.url, format!("has commits by {}", authors.join(", ")));
+            }
+        }
+        prs_state = bot_only;
+    }
+
+    // Merges would fail on reviews the tool can't give.
+    let mut reviewable = Vec::with_capacity(prs_state.len());
+    for pr in prs_state {
+        let state = match reviews.remove(&pr.url) {
+            Some(state) => state,
+            None => review_state(octo, &pr, retry).await?,
+        };
+        match unsatisfiable_reviews(&state, cfg.auto_approve) {
+            Some(reason) => {
+                info!("{:?}: {}", pr.url, reason);
+                report.skip(&pr.url, reason);
+            }
+            None => reviewable.push(pr),
+        }
+    }
+
+    Ok(reviewable)
+}
+
+/// The PRs of `org/repo` worth a closer look, read through the REST API.
+async fn rest_prs(
+    octo: &Octocrab,
+    cfg: &Config,
+    settings: &RepoSettings,
+    org: &str,
+    repo: &str,
+    report: &mut RepoReport,
+) -> octocrab::Result<Vec<DependabotPr>> {
+    let retry = &cfg.retry;
+    let prs: Vec<_> = list_open_prs(octo, org, repo, retry)
+        .await?
+        .into_iter()
+        .filter(|pr| {
+            pr.user
+                .as_ref()
+                .is_some_and(|u| cfg.bot_login.contains(&u.login))
+        })
+        .collect();
+    // Before any filtering, so that PRs held back by policy still count.
+    report.prs_found += prs.len();
+
+    let mut prs_state = Vec::<DependabotPr>::new();
+    let mut to_fetch = Vec::new();
+    // Most PRs target the same branch, so fetch each base branch once.
+    let mut base_shas = HashMap::<String, Option<String>>::new();
+
+    for pr in prs {
+        let title = pr.title.as_deref().unwrap_or("");
+        if let Some(reason) = listing_skip_reason(cfg, settings, title, &pr.base.ref_field) {
+            let html_url = pr.html_url.map(|url| url.to_string()).unwrap_or_default();
+            report.skip(&html_url, reason);
+            continue;
+        }
+        let base_branch_sha = match base_shas.get(&pr.base.ref_field) {
+            Some(sha) => sha.clone(),
+            None => {
+                let ref_url = format!(
+                    "/repos/{}/{}/git/ref/{}",
+                    org,
+                    repo,
+                    Reference::Branch(pr.base.ref_field.clone()).ref_url()
+                );
+                let base_branch: octocrab::models::repos::Ref =
+                    send_with_retry(retry, true, || octo._get(ref_url.as_str())).await?;
+                let sha = ref_commit_sha(octo, org, repo, base_branch.object, retry).await?;
+                base_shas.insert(pr.base.ref_field.clone(), sha.clone());
+                sha
+            }
+        };
+        let Some(base_branch_sha) = base_branch_sha else {
+            let html_url = pr.html_url.map(|url| url.to_string()).unwrap_or_default();
+            error!(
+                "{:?}: base branch {} doesn't point to a commit",
+                html_url, pr.base.ref_field
+            );
+            report.skip(
+                &html_url,
+                format!("base branch {} can't be resolved", pr.base.ref_field),
+            );
+            continue;
+        };
+        to_fetch.push((base_branch_sha, pr));
+    }
+
+    // Bounded so that big backlogs don't trip the secondary rate limits.
+    let fetched: Vec<octocrab::Result<FetchedPr>> = stream::iter(to_fetch)
+        .map(|(base_branch_sha, pr)| async move {
+            fetch_pr(octo, cfg, settings, org, repo, &base_branch_sha, pr).await
+        })
+        .buffered(PR_FETCH_CONCURRENCY)
+        .collect()
+        .await;
+    for fetched in fetched {
+        match fetched? {
+            FetchedPr::Pr(pr) => prs_state.push(*pr),
+            FetchedPr::Skipped { url, reason } => report.skip(&url, reason),
+        }
+    }
+    Ok(prs_state)
+}
+
+const GRAPHQL_PRS_QUERY: &str = "query($owner: String!, $name: String!, $cursor: String) {
+    repository(owner: $owner, name: $name) {
+        pullRequests(states: OPEN, first: 50, after: $cursor) {
+            pageInfo { hasNextPage endCursor }
+            nodes {
+                id
+                number
+                url
+                title
+                body
+                isDraft
+                createdAt
+                author { __typename login }
+                headRefName
+                headRefOid
+                baseRefName
+                baseRef { target { oid } }
+                mergeable
+                mergeStateStatus
+                labels(first: 100) { nodes { name } }
+                reviewDecision
+                protection: baseRef {
+                    branchProtectionRule { requiredApprovingReviewCount requiresCodeOwnerReviews }
+                }
+                latestOpinionatedReviews(first: 100) { nodes { state } }
+                viewerLatestReview { state }
+                reviewRequests(first: 100) {
+                    nodes {
+                        asCodeOwner
+                        requestedReviewer {
+                            ... on User { name: login isViewer }
+                            ... on Bot { name: login }
+                            ... on Mannequin { name: login }
+                            ... on Team { name: combinedSlug }
+                        }
+                    }
+                }
+                commits(last: 1) {
+                    nodes {
+                        commit {
+                            statusCheckRollup {
+                                state
+                                contexts(first: 100) {
+                                    totalCount
+                                    nodes {
+                                        __typename
+                                        ... on CheckRun { name status conclusion }
+                                        ... on StatusContext { context state }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}";
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlRepository {
+    repository: Option<GraphQlPullRequests>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlPullRequests {
+    pull_requests: GraphQlPage<GraphQlPr>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlPage<T> {
+    page_info: PageInfo,
+    nodes: Vec<T>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlNodes<T> {
+    nodes: Vec<T>,
+}
+
+/// A PR with everything `graphql_prs` asks for.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlPr {
+    id: String,
+    number: u64,
+    url: String,
+    title: String,
+    body: String,
+    is_draft: bool,
+    created_at: DateTime<Utc>,
+    /// `None` for deleted accounts.
+    author: Option<GraphQlActor>,
+    head_ref_name: String,
+    head_ref_oid: String,
+    base_ref_name: String,
+    /// `None` when the base branch was deleted.
+    base_ref: Option<GraphQlRef>,
+    /// `MERGEABLE`, `CONFLICTING` or `UNKNOWN`.
+    mergeable: String,
+    /// `mergeable_state` in upper case.
+    merge_state_status: String,
+    labels: GraphQlNodes<GraphQlLabel>,
+    #[serde(flatten)]
+    reviews: ReviewState,
+    /// The head commit.
+    commits: GraphQlNodes<GraphQlPrCommit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlActor {
+    #[serde(rename = "__typename")]
+    typename: String,
+    login: String,
+}
+
+impl GraphQlActor {
+    /// GraphQL leaves the `[bot]` suffix of the REST login out.
+    fn is_bot_login(&self, bot_login: &[String]) -> bool {
+        bot_login.contains(&self.login)
+            || (self.typename == "Bot" && bot_login.contains(&format!("{}[bot]", self.login)))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlRef {
+    target: GraphQlTarget,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlTarget {
+    oid: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlLabel {
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GraphQlPrCommit {
+    commit: GraphQlCommit,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlCommit {
+    /// `None` when nothing reported on the commit.
+    status_check_rollup: Option<StatusCheckRollup>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct StatusCheckRollup {
+    state: String,
+    contexts: StatusCheckContexts,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusCheckContexts {
+    total_count: usize,
+    nodes: Vec<StatusCheckContext>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "__typename")]
+enum StatusCheckContext {
+    CheckRun {
+        name: String,
+        status: String,
+        conclusion: Option<String>,
+    },
+    StatusContext {
+        context: String,
+        state: String,
+    },
+}
+
+impl StatusCheckRollup {
+    /// The check runs and statuses as `commit_checks` would return them.
+    fn check_runs(&self) -> Vec<CheckRun> {
+        let status = |context: &str, state: &str| CommitStatus {
+            context: context.to_string(),
+            state: state.to_lowercase(),
+        };
+        let mut runs: Vec<CheckRun> = self
+            .contexts
+            .nodes
+            .iter()
+            .map(|context| match context {
+                StatusCheckContext::CheckRun {
+                    name,
+                    status,
+                    conclusion,
+                } => CheckRun {
+                    name: name.clone(),
+                    status: status.to_lowercase(),
+                    conclusion: conclusion.as_deref().map(str::to_lowercase),
+                },
+                StatusCheckContext::StatusContext { context, state } => {
+                    status(context, state).as_check_run()
+                }
+            })
+            .collect();
+        if self.contexts.nodes.len() < self.contexts.total_count {
+            runs.push(status("combined status", &self.state).as_check_run());
+        }
+        runs
+    }
+}
+
+/// The PRs of `org/repo` worth a closer look and their review state, read
+/// with a GraphQL query per page of PRs.
+async fn graphql_prs(
+    octo: &Octocrab,
+    cfg: &Config,
+    settings: &RepoSettings,
+    org: &str,
+    repo: &str,
+    report: &mut RepoReport,
+) -> Result<(Vec<DependabotPr>, HashMap<String, ReviewState>), AutomergeError> {
+    let mut nodes = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let variables = serde_json::json!({ "owner": org, "name": repo, "cursor": cursor });
+        let data: GraphQlRepository =
+            graphql(octo, &cfg.retry, true, GRAPHQL_PRS_QUERY, variables).await?;
+        let page = data
+            .repository
+            .ok_or_else(|| AutomergeError::Parse(format!("repo {}/{} not found", org, repo)))?
+            .pull_requests;
+        nodes.extend(page.nodes);
+        match page.page_info.end_cursor {
+            Some(end_cursor) if page.page_info.has_next_page => cursor = Some(end_cursor),
+            _ => break,
+        }
+    }
+
+    let nodes: Vec<GraphQlPr> = nodes
+        .into_iter()
+        .filter(|pr| {
+            pr.author
+                .as_ref()
+                .is_some_and(|author| author.is_bot_login(&cfg.bot_login))
+        })
+        .collect();
+    // Before any filtering, so that PRs held back by policy still count.
+    report.prs_found += nodes.len();
+
+    let mut to_check = Vec::new();
+    for pr in nodes {
+        if let Some(reason) = listing_skip_reason(cfg, settings, &pr.title, &pr.base_ref_name) {
+            report.skip(&pr.url, reason);
+            continue;
+        }
+        let Some(base_branch_sha) = pr.base_ref.as_ref().map(|base| base.target.oid.clone()) else {
+            report.skip(
+                &pr.url,
+                format!("base branch {} can't be resolved", pr.base_ref_name),
+            );
+            continue;
+        };
+        to_check.push((base_branch_sha, pr));
+    }
+
+    // Bounded so that big backlogs don't trip the secondary rate limits.
+    let checked: Vec<octocrab::Result<(bool, GraphQlPr)>> = stream::iter(to_check)
+        .map(|(base_branch_sha, pr)| async move {
+            let rebased =
+                is_up_to_date(octo, cfg, org, repo, &base_branch_sha, &pr.head_ref_oid).await?;
+            Ok((rebased, pr))
+        })
+        .buffered(PR_FETCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut prs = Vec::new();
+    let mut reviews = HashMap::new();
+    for checked in checked {
+        let (rebased, pr) = checked?;
+        let check_runs =
+            pr.commits
+                .nodes
+                .first()
+                .map(|head| match &head.commit.status_check_rollup {
+                    Some(rollup) => rollup.check_runs(),
+                    None => Vec::new(),
+                });
+        let all_checks_pass = judge_checks(cfg, settings, &pr.url, check_runs.as_deref());
+        let labels: Vec<String> = pr
+            .labels
+            .nodes
+            .into_iter()
+            .map(|label| label.name)
+            .collect();
+        let (old_version, new_version) = title_versions(&pr.title, cfg);
+        reviews.insert(pr.url.clone(), pr.reviews);
+        prs.push(DependabotPr {
+            url: pr.url,
+            number: pr.number,
+            repo: Repo {
+                org: org.to_string(),
+                repo: repo.to_string(),
+            },
+            node_id: pr.id,
+            head_ref: pr.head_ref_name,
+            head_sha: pr.head_ref_oid,
+            all_checks_pass,
+            rebased,
+            rebase_in_progress: pr.body.contains("Dependabot is rebasing this PR"),
+            rebase_failed: false,
+            failed_rebases: 0,
+            recreate_requested: false,
+            draft: pr.is_draft,
+            created_at: Some(pr.created_at),
+            mergeable: match pr.mergeable.as_str() {
+                "MERGEABLE" => Some(true),
+                "CONFLICTING" => Some(false),
+                _ => None,
+            },
+            mergeable_state: serde_json::from_value(serde_json::json!(pr
+                .merge_state_status
+                .to_lowercase()))
+            .ok(),
+            is_security: is_security_update(&labels, &pr.body),
+            labels,
+            dependency_name: parse_dependency_from_pr(&pr.title).unwrap_or_default(),
+            old_version: old_version.unwrap_or_default(),
+            new_version: new_version.unwrap_or_default(),
+            is_group: is_group_pr(&pr.title),
+            group_updates: parse_group_updates(&pr.body),
+            merge_sha: None,
+        });
+    }
+    Ok((prs, reviews))
+}
+
+/// Why a PR is ruled out by its title or base branch alone.
+fn listing_skip_reason(
+    cfg: &Config,
+    settings: &RepoSettings,
+    title: &str,
+    base: &str,
+) -> Option<String> {
+    if let Some(reason) = title_skip_reason(title, cfg) {
+        return Some(reason.to_string());
+    }
+    if !settings.base_branches.is_empty()
+        && !settings
+            .base_branches
+            .iter()
+            .any(|pattern| matches_pattern(pattern, base))
+    {
+        return Some(format!("targets {}, not one of base_branches", base));
+    }
+    None
+}
+
+/// What fetching the details of one PR came to.
+enum FetchedPr {
+    Pr(Box<DependabotPr>),
+    Skipped { url: String, reason: String },
+}
+
+/// Fetches the checks and details of a PR whose base branch is at
+/// `base_branch_sha`.
+async fn fetch_pr(
+    octo: &Octocrab,
+    cfg: &Config,
+    settings: &RepoSettings,
+    org: &str,
+    repo: &str,
+    base_branch_sha: &str,
+    pr: octocrab::models::pulls::PullRequest,
+) -> octocrab::Result<FetchedPr> {
+    let retry = &cfg.retry;
+    // octo.checks() does not return all checks for some reason
+    // let checks = octo
+    //     .checks(&org, &repo)
+    //     .list_check_runs_for_git_ref(pr.head.sha.into())
+    //     .send()
+    //     .await?;
+    let check_runs = match commit_checks(octo, org, repo, &pr.head.sha, retry).await {
+        Ok(check_runs) => Some(check_runs),
+        // E.g. the commit was garbage collected after a force push.
+        Err(octocrab::Error::GitHub { source, .. })
+            if source.status_code == StatusCode::NOT_FOUND =>
+        {
+            warn!(
+                "{:?}: checks of {} not found, not merging",
+                pr.html_url
+                    .as_ref()
+                    .map(|url| url.as_str())
+                    .unwrap_or_default(),
+                pr.head.sha
+            );
+            None
+        }
+        Err(e) => return Err(e),
+    };
+
+    // `pr.base.sha` is only the base tip when the PR was last
+    // synchronized, so ask GitHub whether the head is behind the
+    // current tip, i.e. whether their merge base is the tip.
+    let rebased = is_up_to_date(octo, cfg, org, repo, base_branch_sha, &pr.head.sha).await?;
+    let html_url = pr
+        .html_url
+        .as_ref()
+        .map(|url| url.as_str())
+        .unwrap_or_default();
+    let all_checks_pass = judge_checks(cfg, settings, html_url, check_runs.as_deref());
+
+    let checked_sha = pr.head.sha;
+    let url = format!("/repos/{}/{}/pulls/{}", org, repo, pr.number);
+    let pr: octocrab::models::pulls::PullRequest =
+        send_with_retry(retry, true, || octo._get(url.as_str())).await?;
+    // The checks above would be for an outdated commit.
+    if pr.head.sha != checked_sha {
+        info!(
+            "{:?}: head moved from {} to {} while checking, leaving it to the next run",
+            url, checked_sha, pr.head.sha
+        );
+        return Ok(FetchedPr::Skipped {
+            url: pr.html_url.map(|url| url.to_string()).unwrap_or_default(),
+            reason: "new commits pushed while checking".to_string(),
+        });
+    }
+    let (old_version, new_version) = pr
+        .title
+        .as_deref()
+        .map(|title| title_versions(title, cfg))
+        .unwrap_or((None, None));
+    let labels: Vec<String> = pr
+        .labels
+        .unwrap_or_default()
+        .into_iter()
+        .map(|label| label.name)
+        .collect();
+
+    Ok(FetchedPr::Pr(Box::new(DependabotPr {
+        url: pr
+            .html_url
+            .map(|url| url.to_string())
+            .unwrap_or("".to_string()),
+        number: pr.number,
+        repo: Repo {
+            org: org.to_string(),
+            repo: repo.to_string(),
+        },
+        node_id: pr.node_id.unwrap_or_default(),
+        head_ref: pr.head.ref_field,
+        head_sha: pr.head.sha,
+        all_checks_pass,
+        rebased,
+        rebase_in_progress: pr
+            .body
+            .as_deref()
+            .is_some_and(|b| b.contains("Dependabot is rebasing this PR")),
+        rebase_failed: false,
+        failed_rebases: 0,
+        recreate_requested: false,
+        draft: pr.draft.unwrap_or(false),
+        created_at: pr.created_at,
+        mergeable: pr.mergeable,
+        mergeable_state: pr.mergeable_state,
+        is_security: is_security_update(&labels, pr.body.as_deref().unwrap_or_default()),
+        labels,
+        dependency_name: pr
+            .title
+            .as_deref()
+            .and_then(parse_dependency_from_pr)
+            .unwrap_or("".to_string()),
+        old_version: old_version.unwrap_or("".to_string()),
+        new_version: new_version.unwrap_or("".to_string()),
+        is_group: pr.title.as_deref().is_some_and(is_group_pr),
+        group_updates: pr
+            .body
+            .as_deref()
+            .map(parse_group_updates)
+            .unwrap_or_default(),
+        merge_sha: None,
+    })))
+}
+
+/// Whether the head contains the current tip of the base branch. Always
+/// true without `require_up_to_date`.
+async fn is_up_to_date(
+    octo: &Octocrab,
+    cfg: &Config,
+    org: &str,
+    repo: &str,
+    base_branch_sha: &str,
+    head_sha: &str,
+) -> octocrab::Result<bool> {
+    // Stale branches are merged as they are, only conflicts need a rebase.
+    if !cfg.require_up_to_date {
+        return Ok(true);
+    }
+    let compare_url = format!(
+        "/repos/{}/{}/compare/{}...{}?per_page=1",
+        org, repo, base_branch_sha, head_sha
+    );
+    let comparison: Comparison =
+        send_with_retry(&cfg.retry, true, || octo._get(compare_url.as_str())).await?;
+    Ok(comparison.behind_by == 0)
+}
+
+/// Whether the checks allow merging the PR at `url`. Unknown checks don't.
+fn judge_checks(
+    cfg: &Config,
+    settings: &RepoSettings,
+    url: &str,
+    check_runs: Option<&[CheckRun]>,
+) -> bool {
+    let Some(check_runs) = check_runs else {
+        return false;
+    };
+    // E.g. path filters kept CI from running them.
+    let missing = missing_checks(check_runs, &settings.required_checks);
+    if !missing.is_empty() {
+        info!(
+            "{:?}: required checks {} didn't run, not merging",
+            url,
+            missing.join(", ")
+        );
+    }
+    checks_pass(
+        check_runs,
+        &settings.required_checks,
+        settings.min_checks,
+        &cfg.blocking_conclusions,
+    )
+}
+
+/// Review requirements of a PR's base branch and who was asked to review.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewState {
+    /// `APPROVED`, `CHANGES_REQUESTED` or `REVIEW_REQUIRED`. `None` when
+    /// no reviews are required.
+    review_decision: Option<String>,
+    review_requests: ReviewRequests,
+    /// The base branch's protection rule, aliased so that it doesn't clash
+    /// with other `baseRef` fields of the query.
+    #[serde(default)]
+    protection: Option<ReviewProtection>,
+    /// The latest approval or change request of every reviewer.
+    #[serde(default)]
+    latest_opinionated_reviews: Option<GraphQlNodes<ReviewVerdict>>,
+    /// The tool's own latest review.
+    #[serde(default)]
+    viewer_latest_review: Option<ReviewVerdict>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewProtection {
+    branch_protection_rule: Option<ProtectionRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProtectionRule {
+    required_approving_review_count: Option<usize>,
+    #[serde(default)]
+    requires_code_owner_reviews: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReviewVerdict {
+    /// `APPROVED`, `CHANGES_REQUESTED`, `COMMENTED`, etc.
+    state: String,
+}
+
+impl ReviewState {
+    /// Why the approvals branch protection requires can't be reached with
+    /// the tool's own, e.g. on repos requiring two.
+    fn missing_approvals(&self) -> Option<String> {
+        let required = self
+            .protection
+            .as_ref()
+            .and_then(|protection| protection.branch_protection_rule.as_ref())
+            .and_then(|rule| rule.required_approving_review_count)
+            .unwrap_or(0);
+        let is_approval = |review: &ReviewVerdict| review.state == "APPROVED";
+        let approvals = self
+            .latest_opinionated_reviews
+            .as_ref()
+            .map_or(0, |reviews| {
+                reviews.nodes.iter().filter(|r| is_approval(r)).count()
+            });
+        let own = usize::from(!self.viewer_latest_review.as_ref().is_some_and(is_approval));
+        (approvals + own < required).then(|| {
+            format!(
+                "needs {} approvals, has {} besides the tool's",
+                required,
+                (approvals + own).saturating_sub(1)
+            )
+        })
+    }
+
+    fn requires_code_owner_reviews(&self) -> bool {
+        self.protection
+            .as_ref()
+            .and_then(|protection| protection.branch_protection_rule.as_ref())
+            .is_some_and(|rule| rule.requires_code_owner_reviews)
+    }
+
+    /// Who besides the tool was asked to review, only counting code owners
+    /// if `code_owners_only`.
+    fn pending_reviewers(&self, code_owners_only: bool) -> Vec<String> {
+        self.review_requests
+            .nodes
+            .iter()
+            .filter(|request| request.as_code_owner || !code_owners_only)
+            .filter_map(|request| request.requested_reviewer.as_ref())
+            .filter(|reviewer| !reviewer.is_viewer)
+            .filter_map(|reviewer| reviewer.name.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReviewRequests {
+    nodes: Vec<ReviewRequest>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewRequest {
+    /// Requested because of `CODEOWNERS`.
+    #[serde(default)]
+    as_code_owner: bool,
+    requested_reviewer: Option<RequestedReviewer>,
+}
+
+/// A user's login or a team's `org/slug`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestedReviewer {
+    name: Option<String>,
+    /// The tool's own user, whose approval `auto_approve` gives.
+    #[serde(default)]
+    is_viewer: bool,
+}
+
+async fn review_state(
+    octo: &Octocrab,
+    pr: &DependabotPr,
+    retry: &RetryConfig,
+) -> Result<ReviewState, AutomergeError> {
+    let data: PullRequestNode<ReviewState> = graphql(
+        octo,
+        retry,
+        true,
+        "query($id: ID!) {
+            node(id: $id) {
+                ... on PullRequest {
+                    reviewDecision
+                    protection: baseRef {
+                        branchProtectionRule { requiredApprovingReviewCount requiresCodeOwnerReviews }
+                    }
+                    latestOpinionatedReviews(first: 100) { nodes { state } }
+                    viewerLatestReview { state }
+                    reviewRequests(first: 100) {
+                        nodes {
+                            asCodeOwner
+                            requestedReviewer {
+                                ... on User { name: login isViewer }
+                                ... on Bot { name: login }
+                                ... on Mannequin { name: login }
+                                ... on Team { name: combinedSlug }
+                            }
+                        }
+                    }
+                }
+            }
+        }",
+        serde_json::json!({ "id": pr.node_id }),
+    )
+    .await?;
+    Ok(data.node)
+}
+
+/// Why the tool's own approval can't get the PR past its required reviews,
+/// e.g. when other code owners were asked to review. `None` if it can.
+fn unsatisfiable_reviews(state: &ReviewState, auto_approve: bool) -> Option<String> {
+    match state.review_decision.as_deref() {
+        Some("CHANGES_REQUESTED") => Some("changes requested".to_string()),
+        Some("REVIEW_REQUIRED") if !auto_approve => {
+            let pending = state.pending_reviewers(false);
+            Some(if pending.is_empty() {
+                "review required".to_string()
+            } else {
+                format!("waiting for reviews from {}", pending.join(", "))
+            })
+        }
+        Some("REVIEW_REQUIRED") => {
+            // Other requested reviewers only hold the merge up if they're
+            // code owners whose reviews are required.
+            let owners = state.pending_reviewers(true);
+            if state.requires_code_owner_reviews() && !owners.is_empty() {
+                Some(format!(
+                    "waiting for code owner reviews from {}",
+                    owners.join(", ")
+                ))
+            } else {
+                state.missing_approvals()
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Why `title_allow_regex` or `title_deny_regex` rule the PR out.
+fn title_skip_reason(title: &str, cfg: &Config) -> Option<&'static str> {
+    if cfg
+        .title_allow_regex
+        .as_ref()
+        .is_some_and(|re| !re.is_match(title))
+    {
+        Some("title doesn't match title_allow_regex")
+    } else if cfg
+        .title_deny_regex
+        .as_ref()
+        .is_some_and(|re| re.is_match(title))
+    {
+        Some("title matches title_deny_regex")
+    } else {
+        None
+    }
+}
+
+/// All check runs of a commit, plus its commit statuses as check runs:
+/// some CI systems only report the latter.
+async fn commit_checks(
+    octo: &Octocrab,
+    org: &str,
+    repo: &str,
+    sha: &str,
+    retry: &RetryConfig,
+) -> octocrab::Result<Vec<CheckRun>> {
+    let mut runs = Vec::new();
+    // Big CI matrices have more runs than fit on a page.
+    for page in 1.. {
+        let url = format!(
+            "/repos/{}/{}/commits/{}/check-runs?per_page=100&page={}",
+            org, repo, sha, page
+        );
+        let page: CheckRuns = send_with_retry(retry, true, || octo._get(url.as_str())).await?;
+        let last_page = page.check_runs.is_empty();
+        runs.extend(page.check_runs);
+        if last_page || runs.len() >= page.total_count {
+            break;
+        }
+    }
+
+    let url = format!(
+        "/repos/{}/{}/commits/{}/status?per_page=100",
+        org, repo, sha
+    );
+    let status: CombinedStatus = send_with_retry(retry, true, || octo._get(url.as_str())).await?;
+    runs.extend(status.check_runs());
+    Ok(runs)
+}
+
+/// The labels dependabot adds to tell ecosystems apart, next to `dependencies`.
+const ECOSYSTEM_LABELS: &[&str] = &[
+    ".NET",
+    "dart",
+    "docker",
+    "elixir",
+    "elm",
+    "github_actions",
+    "go",
+    "java",
+    "javascript",
+    "php",
+    "python",
+    "ruby",
+    "rust",
+    "submodules",
+    "swift",
+    "terraform",
+];
+
+/// Phrases in the bodies of PRs opened by Dependabot security updates.
+const SECURITY_BODY_MARKERS: &[&str] = &[
+    "dependabot security update",
+    "security vulnerability",
+    "security advisory",
+];
+
+/// Security updates carry a `security` label when the repo has one,
+/// otherwise only their body tells.
+fn is_security_update(labels: &[String], body: &str) -> bool {
+    let body = body.to_lowercase();
+    labels
+        .iter()
+        .any(|label| label.eq_ignore_ascii_case("security"))
+        || SECURITY_BODY_MARKERS
+            .iter()
+            .any(|marker| body.contains(marker))
+}
+
+async fn pr_commits(
+    octo: &Octocrab,
+    pr: &DependabotPr,
+    retry: &RetryConfig,
+) -> octocrab::Result<Vec<PrCommit>> {
+    let url = format!(
+        "/repos/{}/{}/pulls/{}/commits?per_page=100",
+        pr.repo.org, pr.repo.repo, pr.number
+    );
+    get_all_pages(octo, &url, retry).await
+}
+
+/// Authors of the commits that aren't one of `bot_login`.
+fn other_commit_authors(commits: &[PrCommit], bot_login: &[String]) -> Vec<String> {
+    let mut authors = Vec::new();
+    for commit in commits {
+        let author = match &commit.author {
+            Some(user) if bot_login.contains(&user.login) => continue,
+            Some(user) => user.login.clone(),
+            None => "an unknown author".to_string(),
+        };
+        if !authors.contains(&author) {
+            authors.push(author);
+        }
+    }
+    authors
+}
+
+/// The commit a git ref points to. Annotated tags are followed to their
+/// commit, lightweight ones point to it directly. `None` for anything else.
+async fn ref_commit_sha(
+    octo: &Octocrab,
+    org: &str,
+    repo: &str,
+    object: octocrab::models::repos::Object,
+    retry: &RetryConfig,
+) -> octocrab::Result<Option<String>> {
+    use octocrab::models::repos::Object;
+
+    let mut tag_sha = match object {
+        Object::Commit { sha, .. } => return Ok(Some(sha)),
+        Object::Tag { sha, .. } => sha,
+        _ => return Ok(None),
+    };
+    // Tags of tags are possible, but not endlessly.
+    for _ in 0..MAX_TAG_DEPTH {
+        let url = format!("/repos/{}/{}/git/tags/{}", org, repo, tag_sha);
+        let tag: GitTag = send_with_retry(retry, true, || octo._get(url.as_str())).await?;
+        match tag.object.kind.as_str() {
+            "commit" => return Ok(Some(tag.object.sha)),
+            "tag" => tag_sha = tag.object.sha,
+            _ => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+const MAX_TAG_DEPTH: usize = 4;
+
+/// Response of the `/git/tags/{sha}` endpoint.
+#[derive(Debug, Clone, Deserialize)]
+struct GitTag {
+    object: GitObject,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GitObject {
+    sha: String,
+    /// `commit`, `tag`, `tree` or `blob`.
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// PRs with an unknown creation time only pass when no minimum age is set.
+fn is_old_enough(
+    created_at: Option<DateTime<Utc>>,
+    min_age: chrono::Duration,
+    now: DateTime<Utc>,
+) -> bool {
+    match created_at {
+        Some(created_at) => now - created_at >= min_age,
+        None => min_age <= chrono::Duration::zero(),
+    }
+}
+
+async fn pr_comments_since(
+    octo: &Octocrab,
+    pr: &DependabotPr,
+    since: DateTime<Utc>,
+    retry: &RetryConfig,
+) -> octocrab::Result<Vec<IssueComment>> {
+    let url = format!(
+        "/repos/{}/{}/issues/{}/comments?per_page=100&since={}",
+        pr.repo.org,
+        pr.repo.repo,
+        pr.number,
+        since.format("%Y-%m-%dT%H:%M:%SZ")
+    );
+    get_all_pages(octo, &url, retry).await
+}
+
+/// Dependabot comments like this when a rebase fails, e.g. on merge
+/// conflicts it can't resolve.
+const REBASE_FAILURE_PATTERNS: &[&str] = &[
+    "can't rebase",
+    "cannot rebase",
+    "couldn't rebase",
+    "unable to rebase",
+    "conflicts that dependabot can't resolve",
+];
+
+const RECREATE_COMMAND: &str = "@dependabot recreate";
+
+/// Whether the latest comment is dependabot reporting a failed rebase.
+/// Any later comment, e.g. `@dependabot recreate`, clears it.
+fn rebase_failed(comments: &[IssueComment], bot_login: &[String]) -> bool {
+    comments
+        .iter()
+        .max_by_key(|c| c.created_at)
+        .is_some_and(|c| is_rebase_failure(c, bot_login))
+}
+
+fn failed_rebases(comments: &[IssueComment], bot_login: &[String]) -> usize {
+    comments
+        .iter()
+        .filter(|c| is_rebase_failure(c, bot_login))
+        .count()
+}
+
+fn is_rebase_failure(comment: &IssueComment, bot_login: &[String]) -> bool {
+    comment
+        .user
+        .as_ref()
+        .is_some_and(|u| bot_login.contains(&u.login))
+        && comment.body.as_deref().is_some_and(|b| {
+            let b = b.to_lowercase();
+            REBASE_FAILURE_PATTERNS.iter().any(|p| b.contains(p))
+        })
+}
+
+/// Whether one of `bodies`, e.g. rebase commands, was commented after `since`.
+fn has_comment(comments: &[IssueComment], since: DateTime<Utc>, bodies: &[&str]) -> bool {
+    comments.iter().any(|c| {
+        c.created_at >= since
+            && c.body
+                .as_deref()
+                .is_some_and(|b| bodies.contains(&b.trim()))
+    })
+}
+
+/// What to comment to get dependabot to rebase the PR, or `None` when it
+/// gave up and a human needs to step in.
+fn rebase_command<'a>(pr: &DependabotPr, cfg: &'a Config) -> Option<&'a str> {
+    if cfg.recreate_conflicting && pr.has_conflicts() {
+        return (!pr.recreate_requested).then_some(RECREATE_COMMAND);
+    }
+    if !pr.rebase_failed {
+        return Some(&cfg.rebase_command);
+    }
+    match cfg.recreate_after_failed_rebases {
+        Some(_) if pr.recreate_requested => None,
+        Some(limit) if pr.failed_rebases >= limit => Some(RECREATE_COMMAND),
+        Some(_) => Some(&cfg.rebase_command),
+        None => None,
+    }
+}
+
+/// Lists all open PRs, following pagination so none are missed on busy repos.
+async fn list_open_prs(
+    octo: &Octocrab,
+    org: &str,
+    repo: &str,
+    retry: &RetryConfig,
+) -> octocrab::Result<Vec<octocrab::models::pulls::PullRequest>> {
+    let url = format!("/repos/{}/{}/pulls?state=open&per_page=100", org, repo);
+    get_all_pages(octo, &url, retry).await
+}
+
+/// Fetches every page of a list endpoint by following its `Link` headers.
+async fn get_all_pages<T: DeserializeOwned>(
+    octo: &Octocrab,
+    url: &str,
+    retry: &RetryConfig,
+) -> octocrab::Result<Vec<T>> {
+    let mut page: Page<T> = send_with_retry(retry, true, || octo._get(url)).await?;
+    let mut items = page.take_items();
+    while let Some(next) = page.next.take() {
+        page = send_with_retry(retry, true, || octo._get(next.clone())).await?;
+        items.append(&mut page.take_items());
+    }
+    Ok(items)
+}
+
+/// Sends a GitHub API request, retrying with exponential backoff.
+/// Rate limited requests are always retried since GitHub rejects them
+/// before doing anything. Server and connection errors are only retried
+/// for `idempotent` requests, as the original might have been applied.
+async fn send_with_retry<R, F, Fut>(
+    retry: &RetryConfig,
+    idempotent: bool,
+    send: F,
+) -> octocrab::Result<R>
+where
+    R: FromResponse,
+    F: Fn() -> Fut,
+    Fut: Future<Output = octocrab::Result<Response>>,
+{
+    R::from_response(send_with_retry_raw(retry, idempotent, send).await?).await
+}
+
+/// Like `send_with_retry`, but leaves the response body alone, for
+/// endpoints that reply with `204 No Content`.
+async fn send_with_retry_raw<F, Fut>(
+    retry: &RetryConfig,
+    idempotent: bool,
+    send: F,
+) -> octocrab::Result<Response>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = octocrab::Result<Response>>,
+{
+    let mut attempt = 0;
+    loop {
+        let res = send().await;
+        if attempt < retry.max_retries {
+            let backoff = retry.backoff(attempt);
+            let delay = match &res {
+                Ok(resp) => retry_delay(
+                    resp.status(),
+                    resp.headers(),
+                    idempotent,
+                    backoff,
+                    SystemTime::now(),
+                ),
+                Err(octocrab::Error::Hyper { .. } | octocrab::Error::Service { .. })
+                    if idempotent =>
+                {
+                    Some(backoff)
+                }
+                Err(_) => None,
+            };
+            if let Some(delay) = delay {
+                warn!("GitHub request failed, retrying in {:?}", delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+        }
+        return octocrab::map_github_error(res?).await;
+    }
+}
+
+/// Returns how long to wait before retrying a request that got this
+/// response, or `None` if it shouldn't be retried.
+/// `Retry-After` and `X-RateLimit-Reset` take precedence over `backoff`.
+fn retry_delay(
+    status: StatusCode,
+    headers: &HeaderMap,
+    idempotent: bool,
+    backoff: Duration,
+    now: SystemTime,
+) -> Option<Duration> {
+    let header = |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+    let retry_after = header("retry-after");
+    let rate_limit_exhausted = header("x-ratelimit-remaining") == Some(0);
+
+    let delay = if status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN && (retry_after.is_some() || rate_limit_exhausted))
+    {
+        match (retry_after, header("x-ratelimit-reset")) {
+            (Some(secs), _) => Duration::from_secs(secs),
+            (None, Some(reset)) if rate_limit_exhausted => {
+                let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+                Duration::from_secs(reset).saturating_sub(now)
+            }
+            _ => backoff,
+        }
+    } else if idempotent && status.is_server_error() {
+        backoff
+    } else {
+        return None;
+    };
+
+    (delay <= MAX_RETRY_DELAY).then_some(delay)
+}
+
+/// A PR is mergeable only when every check run has completed successfully
+/// and every required check is among them. In-flight checks block the merge.
+/// At least `min_checks` of the runs must have succeeded rather than been
+/// skipped.
+fn checks_pass(
+    check_runs: &[CheckRun],
+    required_checks: &[String],
+    min_checks: usize,
+    blocking_conclusions: &[String],
+) -> bool {
+    // Unfinished runs have no conclusion yet.
+    let passed = |c: &CheckRun| {
+        c.status == "completed"
+            && c.conclusion
+                .as_ref()
+                .is_some_and(|conclusion| !blocking_conclusions.contains(conclusion))
+    };
+    check_runs.iter().all(passed)
+        && required_checks
+            .iter()
+            .all(|name| check_runs.iter().any(|c| &c.name == name && passed(c)))
+        && check_runs
+            .iter()
+            .filter(|c| c.conclusion.as_deref() == Some("success"))
+            .count()
+            >= min_checks
+}
+
+/// The `required_checks` that aren't among the check runs at all, which
+/// `checks_pass` fails the PR for like it does for failed ones.
+fn missing_checks<'a>(check_runs: &[CheckRun], required_checks: &'a [String]) -> Vec<&'a str> {
+    required_checks
+        .iter()
+        .filter(|name| !check_runs.iter().any(|c| &c.name == *name))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Matches versions like `1.2.3`, `1.2.4-alpha.1+build.1` and `1.2.3a0+210.bafdcd99`.
+const VERSION_PATTERN: &str = r"\d+\.\d+\.\d+(-[a-zA-Z0-9\.]+)?(a0)?(\+[a-zA-Z0-9\.]+)?";
+
+/// How a dependency is versioned, going by what dependabot puts in titles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionKind {
+    /// `1.2.3`, optionally with pre-release and build suffixes.
+    Semver,
+    /// Fewer components, e.g. Docker tags like `3.19`, `20` or `3.19-alpine`.
+    Short,
+    /// Go pseudo-versions of untagged commits, e.g.
+    /// `0.0.0-20220127200216-cd36cc0744dd`.
+    GoPseudo,
+    /// Docker image digests and git commit SHAs, e.g. `1a2b3c4`.
+    Digest,
+}
+
+pub fn version_kind(version: &str) -> Option<VersionKind> {
+    let go_pseudo =
+        Regex::new(r"^v?\d+\.\d+\.\d+-(?:[0-9A-Za-z.]*\.)?\d{14}-[0-9a-f]{12}(?:\+incompatible)?$")
+            .unwrap();
+    let semver = Regex::new(&format!("^v?{}$", VERSION_PATTERN)).unwrap();
+    let short = Regex::new(r"^v?\d+(?:\.\d+)?(?:-[a-zA-Z0-9.]+)?$").unwrap();
+    let digest = Regex::new(r"^(?:sha256:)?[0-9a-f]{7,64}$").unwrap();
+    if go_pseudo.is_match(version) {
+        Some(VersionKind::GoPseudo)
+    } else if semver.is_match(version) {
+        Some(VersionKind::Semver)
+    } else if short.is_match(version) {
+        Some(VersionKind::Short)
+    } else if digest.is_match(version) {
+        Some(VersionKind::Digest)
+    } else {
+        None
+    }
+}
+
+/// A version as it appears in a title, where digests are in backticks.
+/// `None` unless it's a kind of version we know.
+fn title_version(token: &str) -> Option<String> {
+    let version = token.trim_matches('`').trim_end_matches(['.', ',', ')']);
+    version_kind(version).map(|_| version.to_string())
+}
+
+pub fn parse_version_from_pr(title: &str) -> Option<String> {
+    let re = Regex::new(r"\bto (`?[^\s`]+`?)").unwrap();
+    re.captures(title)
+        .and_then(|captures| title_version(&captures[1]))
+}
+
+/// The pre-release part of a version, e.g. `alpha.1` in `1.2.4-alpha.1+build.1`.
+/// Build metadata alone doesn't make a version a pre-release. Go
+/// pseudo-versions are all pre-release, they name an untagged commit.
+pub fn prerelease_of(version: &str) -> Option<&str> {
+    if version_kind(version) == Some(VersionKind::GoPseudo) {
+        return version.split_once('-').map(|(_, prerelease)| prerelease);
+    }
+    let re = Regex::new(&format!("^v?{}$", VERSION_PATTERN)).unwrap();
+    let captures = re.captures(version)?;
+    captures
+        .get(1)
+        .map(|m| m.as_str().trim_start_matches('-'))
+        .or_else(|| captures.get(2).map(|m| m.as_str()))
+}
+
+/// Parses both the old and the new version from titles like
+/// "Bump foo from 1.2.3 to 1.2.4".
+pub fn parse_version_range(title: &str) -> (Option<String>, Option<String>) {
+    let re = Regex::new(r"\bfrom (`?[^\s`]+`?) to ").unwrap();
+    let old_version = re
+        .captures(title)
+        .and_then(|captures| title_version(&captures[1]));
+    (old_version, parse_version_from_pr(title))
+}
+
+/// `parse_version_range` with the new version found by `version_regex`
+/// where that matches.
+fn title_versions(title: &str, cfg: &Config) -> (Option<String>, Option<String>) {
+    let (old_version, new_version) = parse_version_range(title);
+    let custom = cfg
+        .version_regex
+        .as_ref()
+        .and_then(|re| re.captures(title)?.get(1))
+        .map(|m| m.as_str().trim_matches('`').to_string());
+    (old_version, custom.or(new_version))
+}
+
+/// Parses "foo" out of titles like "Bump foo from 1.2.3 to 1.2.4",
+/// "chore(deps): bump @types/node from ..." or "Update foo requirement from ...".
+/// Grouped updates ("Bump the aws group with 3 updates") name no single
+/// dependency and yield `None`.
+pub fn parse_dependency_from_pr(title: &str) -> Option<String> {
+    let re = Regex::new(r"(?i)\b(?:bump|update) (\S+)(?: requirement)? from ").unwrap();
+    re.captures(title)
+        .and_then(|captures| captures.get(1).map(|m| m.as_str().to_string()))
+}
+
+/// Recognizes grouped updates like "Bump the npm-dependencies group with 5 updates"
+/// or "Bump the cargo group across 1 directory with 2 updates".
+fn is_group_pr(title: &str) -> bool {
+    let re = Regex::new(r"(?i)\b(?:bump|update) the \S+ group\b").unwrap();
+    re.is_match(title)
+}
+
+/// Parses the members of a grouped update from the PR body, which has a
+/// line like "Updates `serde` from 1.0.197 to 1.0.198" for each of them.
+fn parse_group_updates(body: &str) -> Vec<DependencyUpdate> {
+    let re = Regex::new(r"(?m)^Updates `([^`]+)` from (\S+) to (\S+?)\.?$").unwrap();
+    re.captures_iter(body)
+        .map(|captures| DependencyUpdate {
+            name: captures[1].to_string(),
+            old_version: captures[2].to_string(),
+            new_version: captures[3].to_string(),
+        })
+        .collect()
+}
+
+/// Matches `name` against a pattern where `*` stands for any characters.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let re = format!(
+        "^{}$",
+        pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*")
+    );
+    Regex::new(&re).unwrap().is_match(name)
+}
+
+/// Why a PR with these labels isn't eligible, if it isn't.
+fn label_skip_reason(labels: &[String], require: &[String], exclude: &[String]) -> Option<String> {
+    if let Some(label) = require.iter().find(|label| !labels.contains(label)) {
+        return Some(format!("missing label {:?}", label));
+    }
+    if let Some(label) = exclude.iter().find(|label| labels.contains(label)) {
+        return Some(format!("has label {:?}", label));
+    }
+    None
+}
+
+/// Classifies the change between two versions by comparing their numeric
+/// `major.minor.patch` components. Pre-release and build suffixes
+/// (`-alpha.1`, `a0+201.fbdbcb12`) are ignored, so a change in those only
+/// counts as a patch bump. Digests have no bump size.
+pub fn bump_level(old_version: &str, new_version: &str) -> Option<Bump> {
+    let old = numeric_version(old_version)?;
+    let new = numeric_version(new_version)?;
+
+    let component = |v: &[u64], i: usize| v.get(i).copied().unwrap_or(0);
+    if component(&old, 0) != component(&new, 0) {
+        Some(Bump::Major)
+    } else if component(&old, 1) != component(&new, 1) {
+        Some(Bump::Minor)
+    } else {
+        Some(Bump::Patch)
+    }
+}
+
+/// Orders versions by their numeric components, e.g. `1.10` after `1.9`.
+/// Pre-releases are ordered like their release. `None` for digests and
+/// unknown formats.
+fn compare_versions(a: &str, b: &str) -> Option<std::cmp::Ordering> {
+    let a = numeric_version(a)?;
+    let b = numeric_version(b)?;
+    let component = |v: &[u64], i: usize| v.get(i).copied().unwrap_or(0);
+    let len = a.len().max(b.len());
+    Some(
+        (0..len)
+            .map(|i| component(&a, i).cmp(&component(&b, i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal),
+    )
+}
+
+/// The leading numeric components of a version, e.g. `[1, 2, 4]` for
+/// `v1.2.4-alpha.1`. `None` for digests, which have no order.
+fn numeric_version(version: &str) -> Option<Vec<u64>> {
+    if version_kind(version) == Some(VersionKind::Digest) {
+        return None;
+    }
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+    version[..end]
+        .split('.')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()
+        .filter(|v| !v.is_empty())
+}
+
+/// A newer open PR updating the same dependency in the same place, which
+/// dependabot will close `pr` in favor of.
+fn superseded_by<'a>(pr: &DependabotPr, prs: &'a [DependabotPr]) -> Option<&'a DependabotPr> {
+    // Dependabot's branches are `dependabot/<ecosystem>/<directory>/<name>-<version>`.
+    let location = |pr: &DependabotPr| pr.head_ref.rsplit_once('/').map(|(dir, _)| dir.to_string());
+    if pr.is_group || pr.dependency_name.is_empty() {
+        return None;
+    }
+    prs.iter().find(|other| {
+        other.number != pr.number
+            && !other.is_group
+            && other.dependency_name == pr.dependency_name
+            && location(other) == location(pr)
+            && match compare_versions(&other.new_version, &pr.new_version) {
+                Some(std::cmp::Ordering::Greater) => true,
+                Some(std::cmp::Ordering::Equal) => other.number > pr.number,
+                _ => false,
+            }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use wiremock::matchers::{
+        body_partial_json, method, path, path_regex, query_param, query_param_is_missing,
+    };
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn mock_octocrab(server: &MockServer) -> Octocrab {
+        let mut builder = Octocrab::builder();
+        builder.add_retry_config(octocrab::service::middleware::retry::RetryConfig::None);
+        builder.base_uri(server.uri()).unwrap().build().unwrap()
+    }
+
+    fn author_json(login: &str) -> serde_json::Value {
+        let url = format!("https://api.github.com/users/{}", login);
+        serde_json::json!({
+            "login": login,
+            "id": 1,
+            "node_id": "node",
+            "avatar_url": url,
+            "gravatar_id": "",
+            "url": url,
+            "html_url": url,
+            "followers_url": url,
+            "following_url": url,
+            "gists_url": url,
+            "starred_url": url,
+            "subscriptions_url": url,
+            "organizations_url": url,
+            "repos_url": url,
+            "events_url": url,
+            "received_events_url": url,
+            "type": "Bot",
+            "site_admin": false,
+        })
+    }
+
+    fn pr_json(number: u64) -> serde_json::Value {
+        serde_json::json!({
+            "url": format!("https://api.github.com/repos/org/repo/pulls/{}", number),
+            "html_url": format!("https://github.com/org/repo/pull/{}", number),
+            "id": number,
+            "node_id": format!("PR_{}", number),
+            "number": number,
+            "title": format!("Bump dep{} from 1.0.0 to 1.0.1", number),
+            "user": author_json("dependabot[bot]"),
+            "draft": false,
+            "head": { "ref": format!("dependabot/{}", number), "sha": format!("head{}", number) },
+            "base": { "ref": "main", "sha": "base" },
+        })
+    }
+
+    fn merge_json() -> serde_json::Value {
+        serde_json::json!({ "sha": "merge1", "merged": true, "message": "Pull Request successfully merged" })
+    }
+
+    async fn mount_comparison(server: &MockServer, head_sha: &str, behind_by: u64) {
+        Mock::given(method("GET"))
+            .and(path(format!("/repos/org/repo/compare/base...{}", head_sha)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "status": if behind_by == 0 { "ahead" } else { "diverged" },
+                "ahead_by": 1,
+                "behind_by": behind_by,
+            })))
+            .mount(server)
+            .await;
+    }
+
+    /// Serves the endpoints `dependabot_prs_passing_checks` reads for `prs`:
+    /// green checks and a `main` branch at the PRs' base.
+    async fn mount_prs(server: &MockServer, prs: &[serde_json::Value]) {
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "full_name": "org/repo",
+                "archived": false,
+                "disabled": false,
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(prs))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/git/ref/heads/main"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ref": "refs/heads/main",
+                "node_id": "node",
+                "url": "https://api.github.com/repos/org/repo/git/refs/heads/main",
+                "object": {
+                    "type": "commit",
+                    "sha": "base",
+                    "url": "https://api.github.com/repos/org/repo/git/commits/base",
+                },
+            })))
+            .mount(server)
+            .await;
+        for pr in prs {
+            mount_comparison(server, pr["head"]["sha"].as_str().unwrap(), 0).await;
+            Mock::given(method("GET"))
+                .and(path(format!("/repos/org/repo/pulls/{}", pr["number"])))
+                .respond_with(ResponseTemplate::new(200).set_body_json(pr))
+                .mount(server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/org/repo/commits/{}/check-runs",
+                    pr["head"]["sha"].as_str().unwrap()
+                )))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(serde_json::json!({ "total_count": 0, "check_runs": [] })),
+                )
+                .mount(server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/org/repo/commits/{}/status",
+                    pr["head"]["sha"].as_str().unwrap()
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(
+                    serde_json::json!({ "state": "pending", "total_count": 0, "statuses": [] }),
+                ))
+                .mount(server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/org/repo/pulls/{}/commits",
+                    pr["number"]
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                    { "sha": pr["head"]["sha"], "author": pr["user"] },
+                ])))
+                .mount(server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": {
+                    "node": { "reviewDecision": null, "reviewRequests": { "nodes": [] } },
+                },
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_list_prs() {
+        let server = MockServer::start().await;
+        let mut draft = pr_json(2);
+        draft["draft"] = true.into();
+        mount_prs(&server, &[pr_json(1), draft]).await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/broken"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        let cfg = test_config(r#"repos = ["org/repo", "org/broken"]"#);
+
+        let (listings, failed) = list_prs(&mock_octocrab(&server), &cfg).await;
+        assert_eq!(failed, vec!["org/broken"]);
+        assert_eq!(
+            listings,
+            vec![
+                PrListing {
+                    repo: "org/repo".to_string(),
+                    number: "2".to_string(),
+                    dependency: None,
+                    version: None,
+                    rebased: None,
+                    checks_pass: None,
+                    status: "skip: draft".to_string(),
+                    pending: false,
+                },
+                PrListing {
+                    repo: "org/repo".to_string(),
+                    number: "1".to_string(),
+                    dependency: Some("dep1".to_string()),
+                    version: Some("1.0.1".to_string()),
+                    rebased: Some(true),
+                    checks_pass: Some(true),
+                    status: "eligible".to_string(),
+                    pending: true,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_doctor() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rate_limit"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-oauth-scopes", "read:org, workflow")
+                    .set_body_json(serde_json::json!({})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "full_name": "org/repo",
+                "permissions": { "pull": true, "push": false },
+            })))
+            .mount(&server)
+            .await;
+        let octo = mock_octocrab(&server);
+
+        for (repos, can_act) in [
+            (r#"repos = ["org/repo"]"#, false),
+            (
+                r#"repos = [{ name = "org/repo", action = "approve_only" }]"#,
+                true,
+            ),
+        ] {
+            let checks = doctor(&octo, &test_config(repos)).await;
+            let results: Vec<(&str, bool)> = checks
+                .iter()
+                .map(|check| (check.name.as_str(), check.ok))
+                .collect();
+            assert_eq!(
+                results,
+                vec![
+                    ("API connectivity", true),
+                    ("token scopes", false),
+                    ("org/repo: read PRs", true),
+                    ("org/repo: merge PRs", can_act),
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_skips_ignored_dependencies() {
+        let server = MockServer::start().await;
+        mount_prs(&server, &[pr_json(1), pr_json(2), pr_json(30)]).await;
+        let cfg = test_config(
+            r#"repos = [{ name = "org/repo", ignore_dependencies = ["dep3*", "dep2"] }]"#,
+        );
+
+        let mut report = RepoReport::new("org/repo");
+
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(prs.iter().map(|pr| pr.number).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(prs[0].dependency_name, "dep1");
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_filters_ecosystems() {
+        let server = MockServer::start().await;
+        let label = |name: &str| {
+            serde_json::json!({
+                "id": 1,
+                "node_id": "label",
+                "url": format!("https://api.github.com/repos/org/repo/labels/{}", name),
+                "name": name,
+                "color": "000000",
+                "default": false,
+            })
+        };
+        let mut pip = pr_json(1);
+        pip["head"]["ref"] = "dependabot/pip/dep1-1.0.1".into();
+        pip["labels"] = serde_json::json!([label("dependencies")]);
+        let mut actions = pr_json(2);
+        actions["labels"] = serde_json::json!([label("dependencies"), label("github_actions")]);
+        let mut docker = pr_json(3);
+        docker["head"]["ref"] = "dependabot/docker/dep3-1.0.1".into();
+        mount_prs(&server, &[pip, actions, docker]).await;
+        let octo = mock_octocrab(&server);
+        let numbers = |cfg: Config| {
+            let octo = &octo;
+            async move {
+                let mut report = RepoReport::new("org/repo");
+                let settings = cfg.repo_settings(&cfg.repos[0]);
+                let prs = dependabot_prs_passing_checks(octo, &cfg, &settings, &mut report)
+                    .await
+                    .unwrap();
+                let mut numbers: Vec<u64> = prs.iter().map(|pr| pr.number).collect();
+                numbers.sort();
+                (numbers, report.events)
+            }
+        };
+
+        let (merged, skipped) = numbers(test_config(
+            r#"
+            ecosystems = ["github_actions", "docker"]
+            repos = ["org/repo"]
+            "#,
+        ))
+        .await;
+        assert_eq!(merged, vec![2, 3]);
+        assert_eq!(skipped[0].reason.as_deref(), Some("not one of ecosystems"));
+
+        let (merged, skipped) = numbers(test_config(
+            r#"
+            ecosystems = ["github_actions", "docker"]
+            repos = [{ name = "org/repo", ecosystems = [], ignore_ecosystems = ["dock*"] }]
+            "#,
+        ))
+        .await;
+        assert_eq!(merged, vec![1, 2]);
+        assert_eq!(
+            skipped[0].reason.as_deref(),
+            Some("docker is an ignored ecosystem")
+        );
+
+        // Labels that aren't ecosystems don't match.
+        let (merged, _) = numbers(test_config(
+            r#"
+            ecosystems = ["dependencies"]
+            repos = ["org/repo"]
+            "#,
+        ))
+        .await;
+        assert!(merged.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prs_filtered_by_title_count_as_found() {
+        let server = MockServer::start().await;
+        mount_prs(&server, &[pr_json(1)]).await;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_partial_json(
+                serde_json::json!({ "variables": { "owner": "org" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "repository": { "pullRequests": {
+                    "pageInfo": { "hasNextPage": false, "endCursor": null },
+                    "nodes": [graphql_pr_json(1, serde_json::json!([]))],
+                } } },
+            })))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let octo = mock_octocrab(&server);
+
+        for use_graphql in [false, true] {
+            let cfg = test_config(&format!(
+                r#"
+                repos = ["org/repo"]
+                title_deny_regex = "dep1"
+                use_graphql = {}
+                "#,
+                use_graphql
+            ));
+            let github = GitHub::new(&octo, &cfg.retry);
+            let ctx = RunContext::new(&github, None, &cfg, true);
+            let mut report = RepoReport::new("org/repo");
+            check_prs(&ctx, &cfg.repo_settings(&cfg.repos[0]), &mut report)
+                .await
+                .unwrap();
+            assert_eq!(report.prs_found, 1);
+            let run = RunReport {
+                repos: vec![report],
+                ..RunReport::default()
+            };
+            assert_eq!(run.outcome(), RunOutcome::AllSkipped);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_filters_base_branches() {
+        let server = MockServer::start().await;
+        mount_prs(&server, &[pr_json(1)]).await;
+        let octo = mock_octocrab(&server);
+
+        let cfg = test_config(r#"repos = [{ name = "org/repo", base_branches = ["release/*"] }]"#);
+        let mut report = RepoReport::new("org/repo");
+        let settings = cfg.repo_settings(&cfg.repos[0]);
+        let prs = dependabot_prs_passing_checks(&octo, &cfg, &settings, &mut report)
+            .await
+            .unwrap();
+        assert!(prs.is_empty());
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("targets main, not one of base_branches")
+        );
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            base_branches = ["main", "release/*"]
+            "#,
+        );
+        let mut report = RepoReport::new("org/repo");
+        let settings = cfg.repo_settings(&cfg.repos[0]);
+        let prs = dependabot_prs_passing_checks(&octo, &cfg, &settings, &mut report)
+            .await
+            .unwrap();
+        assert_eq!(prs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_reports_read_only_repos() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "full_name": "org/repo",
+                "permissions": { "admin": false, "maintain": false, "push": false, "triage": true, "pull": true },
+            })))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        mount_prs(&server, &[pr_json(1)]).await;
+        let octo = mock_octocrab(&server);
+
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let mut report = RepoReport::new("org/repo");
+        let prs = dependabot_prs_passing_checks(
+            &octo,
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(prs.len(), 1);
+        assert!(report.read_only);
+        assert!(report
+            .error
+            .as_deref()
+            .is_some_and(|error| error.starts_with("no write permission")));
+
+        let cfg = test_config(r#"repos = [{ name = "org/repo", action = "approve_only" }]"#);
+        let mut report = RepoReport::new("org/repo");
+        let prs = dependabot_prs_passing_checks(
+            &octo,
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(prs.len(), 1);
+        assert!(report.read_only);
+        assert_eq!(report.error, None);
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_survives_missing_check_runs() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/commits/head1/check-runs"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "message": "No commit found for SHA: head1",
+                "documentation_url": "https://docs.github.com/rest",
+            })))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        mount_prs(&server, &[pr_json(1), pr_json(2)]).await;
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+
+        let mut report = RepoReport::new("org/repo");
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        let checks: Vec<(u64, bool)> = prs
+            .iter()
+            .map(|pr| (pr.number, pr.all_checks_pass))
+            .collect();
+        assert_eq!(checks, vec![(1, false), (2, true)]);
+    }
+
+    fn graphql_pr_json(number: u64, contexts: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "id": format!("PR_{}", number),
+            "number": number,
+            "url": format!("https://github.com/org/repo/pull/{}", number),
+            "title": format!("Bump dep{} from 1.0.0 to 1.0.1", number),
+            "body": "",
+            "isDraft": false,
+            "createdAt": "2024-05-01T12:00:00Z",
+            "author": { "__typename": "Bot", "login": "dependabot" },
+            "headRefName": format!("dependabot/{}", number),
+            "headRefOid": format!("head{}", number),
+            "baseRefName": "main",
+            "baseRef": { "target": { "oid": "base" } },
+            "mergeable": "MERGEABLE",
+            "mergeStateStatus": "CLEAN",
+            "labels": { "nodes": [] },
+            "reviewDecision": null,
+            "reviewRequests": { "nodes": [] },
+            "commits": { "nodes": [{ "commit": { "statusCheckRollup": {
+                "state": "SUCCESS",
+                "contexts": { "totalCount": contexts.as_array().unwrap().len(), "nodes": contexts },
+            } } }] },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_with_graphql() {
+        let server = MockServer::start().await;
+        mount_prs(&server, &[pr_json(1), pr_json(2)]).await;
+        let failing = serde_json::json!([
+            { "__typename": "CheckRun", "name": "test", "status": "COMPLETED", "conclusion": "FAILURE" },
+        ]);
+        let passing = serde_json::json!([
+            { "__typename": "CheckRun", "name": "test", "status": "COMPLETED", "conclusion": "SUCCESS" },
+            { "__typename": "StatusContext", "context": "ci/legacy", "state": "SUCCESS" },
+        ]);
+        let mut review_required = graphql_pr_json(3, passing.clone());
+        review_required["reviewDecision"] = "REVIEW_REQUIRED".into();
+        review_required["protection"] = serde_json::json!({
+            "branchProtectionRule": { "requiresCodeOwnerReviews": true },
+        });
+        review_required["reviewRequests"]["nodes"] = serde_json::json!([
+            { "asCodeOwner": true, "requestedReviewer": { "name": "org/owners" } },
+        ]);
+        let mut human = graphql_pr_json(4, passing.clone());
+        human["author"] = serde_json::json!({ "__typename": "User", "login": "octocat" });
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_partial_json(
+                serde_json::json!({ "variables": { "owner": "org" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "repository": { "pullRequests": {
+                    "pageInfo": { "hasNextPage": false, "endCursor": null },
+                    "nodes": [
+                        graphql_pr_json(1, failing),
+                        graphql_pr_json(2, passing),
+                        review_required,
+                        human,
+                    ],
+                } } },
+            })))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        for number in [3, 4] {
+            mount_comparison(&server, &format!("head{}", number), 0).await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/3/commits"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "sha": "head3", "author": author_json("dependabot[bot]") },
+            ])))
+            .mount(&server)
+            .await;
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            use_graphql = true
+            "#,
+        );
+
+        let mut report = RepoReport::new("org/repo");
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        let checks: Vec<(u64, bool)> = prs
+            .iter()
+            .map(|pr| (pr.number, pr.all_checks_pass))
+            .collect();
+        assert_eq!(checks, vec![(1, false), (2, true)]);
+        assert_eq!(prs[1].node_id, "PR_2");
+        assert_eq!(prs[1].new_version, "1.0.1");
+        assert_eq!(prs[1].mergeable_state, Some(MergeableState::Clean));
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("waiting for code owner reviews from org/owners")
+        );
+        // The REST listing wasn't needed.
+        let requests = server.received_requests().await.unwrap();
+        assert!(!requests
+            .iter()
+            .any(|request| request.url.path() == "/repos/org/repo/pulls"));
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_skips_prs_pushed_to_while_checking() {
+        let server = MockServer::start().await;
+        let mut pushed = pr_json(1);
+        pushed["head"]["sha"] = "head1-new".into();
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(pushed))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        mount_prs(&server, &[pr_json(1), pr_json(2)]).await;
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+
+        let mut report = RepoReport::new("org/repo");
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(prs.iter().map(|pr| pr.number).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("new commits pushed while checking")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_skips_human_commits() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/2/commits"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "sha": "bump", "author": author_json("dependabot[bot]") },
+                { "sha": "head2", "author": author_json("octocat") },
+            ])))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        mount_prs(&server, &[pr_json(1), pr_json(2)]).await;
+        let octo = mock_octocrab(&server);
+        let numbers = |prs: Vec<DependabotPr>| prs.iter().map(|pr| pr.number).collect::<Vec<_>>();
+
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let mut report = RepoReport::new("org/repo");
+        let prs = dependabot_prs_passing_checks(
+            &octo,
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(prs), vec![1]);
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("has commits by octocat")
+        );
+
+        let cfg = test_config("repos = [\"org/repo\"]\nallow_human_commits = true");
+        let prs = dependabot_prs_passing_checks(
+            &octo,
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut RepoReport::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(prs), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_skips_prs_waiting_for_other_code_owners() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_partial_json(
+                serde_json::json!({ "variables": { "id": "PR_2" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": {
+                    "node": {
+                        "reviewDecision": "REVIEW_REQUIRED",
+                        "protection": {
+                            "branchProtectionRule": { "requiresCodeOwnerReviews": true },
+                        },
+                        "reviewRequests": {
+                            "nodes": [
+                                {
+                                    "asCodeOwner": true,
+                                    "requestedReviewer": { "name": "octocat" },
+                                },
+                                {
+                                    "asCodeOwner": true,
+                                    "requestedReviewer": { "name": "automerge", "isViewer": true },
+                                },
+                                {
+                                    "asCodeOwner": false,
+                                    "requestedReviewer": { "name": "org/security" },
+                                },
+                            ],
+                        },
+                    },
+                },
+            })))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        mount_prs(&server, &[pr_json(1), pr_json(2)]).await;
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+
+        let mut report = RepoReport::new("org/repo");
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(prs.iter().map(|pr| pr.number).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("waiting for code owner reviews from octocat")
+        );
+    }
+
+    #[test]
+    fn test_unsatisfiable_reviews() {
+        let state = |decision: Option<&str>, reviewers: &[&str]| ReviewState {
+            review_decision: decision.map(str::to_string),
+            review_requests: ReviewRequests {
+                nodes: reviewers
+                    .iter()
+                    .map(|name| ReviewRequest {
+                        as_code_owner: false,
+                        requested_reviewer: Some(RequestedReviewer {
+                            name: Some(name.to_string()),
+                            is_viewer: false,
+                        }),
+                    })
+                    .collect(),
+            },
+            protection: None,
+            latest_opinionated_reviews: None,
+            viewer_latest_review: None,
+        };
+        assert_eq!(unsatisfiable_reviews(&state(None, &[]), false), None);
+        assert_eq!(
+            unsatisfiable_reviews(&state(Some("APPROVED"), &[]), false),
+            None
+        );
+        assert_eq!(
+            unsatisfiable_reviews(&state(Some("REVIEW_REQUIRED"), &[]), true),
+            None
+        );
+        assert_eq!(
+            unsatisfiable_reviews(&state(Some("REVIEW_REQUIRED"), &[]), false).as_deref(),
+            Some("review required")
+        );
+        // The tool's approval is enough when the other reviewers are optional.
+        assert_eq!(
+            unsatisfiable_reviews(&state(Some("REVIEW_REQUIRED"), &["org/owners"]), true),
+            None
+        );
+        assert_eq!(
+            unsatisfiable_reviews(&state(Some("REVIEW_REQUIRED"), &["org/owners"]), false)
+                .as_deref(),
+            Some("waiting for reviews from org/owners")
+        );
+        let code_owners = |is_viewer: bool| {
+            let mut state = state(Some("REVIEW_REQUIRED"), &["org/owners"]);
+            state.protection = Some(ReviewProtection {
+                branch_protection_rule: Some(ProtectionRule {
+                    required_approving_review_count: Some(1),
+                    requires_code_owner_reviews: true,
+                }),
+            });
+            let request = &mut state.review_requests.nodes[0];
+            request.as_code_owner = true;
+            request.requested_reviewer.as_mut().unwrap().is_viewer = is_viewer;
+            state
+        };
+        assert_eq!(
+            unsatisfiable_reviews(&code_owners(false), true).as_deref(),
+            Some("waiting for code owner reviews from org/owners")
+        );
+        assert_eq!(unsatisfiable_reviews(&code_owners(true), true), None);
+        assert_eq!(
+            unsatisfiable_reviews(&state(Some("CHANGES_REQUESTED"), &[]), true).as_deref(),
+            Some("changes requested")
+        );
+
+        let review = |state: &str| ReviewVerdict {
+            state: state.to_string(),
+        };
+        let two_required = |approvals: &[&str], own: Option<&str>| ReviewState {
+            protection: Some(ReviewProtection {
+                branch_protection_rule: Some(ProtectionRule {
+                    required_approving_review_count: Some(2),
+                    requires_code_owner_reviews: false,
+                }),
+            }),
+            latest_opinionated_reviews: Some(GraphQlNodes {
+                nodes: approvals.iter().map(|state| review(state)).collect(),
+            }),
+            viewer_latest_review: own.map(review),
+            ..state(Some("REVIEW_REQUIRED"), &[])
+        };
+        assert_eq!(
+            unsatisfiable_reviews(&two_required(&[], None), true).as_deref(),
+            Some("needs 2 approvals, has 0 besides the tool's")
+        );
+        assert_eq!(
+            unsatisfiable_reviews(&two_required(&["APPROVED"], None), true),
+            None
+        );
+        // The tool's approval is among them already.
+        assert_eq!(
+            unsatisfiable_reviews(&two_required(&["APPROVED"], Some("APPROVED")), true).as_deref(),
+            Some("needs 2 approvals, has 0 besides the tool's")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ref_commit_sha() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/git/tags/outer"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "object": { "type": "tag", "sha": "inner" },
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/git/tags/inner"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "object": { "type": "commit", "sha": "base" },
+            })))
+            .mount(&server)
+            .await;
+        let octo = mock_octocrab(&server);
+        let object = |kind: &str, sha: &str| {
+            serde_json::from_value(serde_json::json!({
+                "type": kind,
+                "sha": sha,
+                "url": format!("https://api.github.com/repos/org/repo/git/{}s/{}", kind, sha),
+            }))
+            .unwrap()
+        };
+
+        let sha = ref_commit_sha(&octo, "org", "repo", object("commit", "base"), &no_retry())
+            .await
+            .unwrap();
+        assert_eq!(sha.as_deref(), Some("base"));
+        let sha = ref_commit_sha(&octo, "org", "repo", object("tag", "outer"), &no_retry())
+            .await
+            .unwrap();
+        assert_eq!(sha.as_deref(), Some("base"));
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_skips_archived_repos() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "full_name": "org/repo",
+                "archived": true,
+            })))
+            .mount(&server)
+            .await;
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+
+        // Listing the PRs would fail, nothing else is mounted.
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut RepoReport::default(),
+        )
+        .await
+        .unwrap();
+        assert!(prs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_matches_configured_bot_login() {
+        let server = MockServer::start().await;
+        let mut custom_bot = pr_json(2);
+        custom_bot["user"] = author_json("renovate[bot]");
+        let mut human = pr_json(3);
+        human["user"] = author_json("octocat");
+        mount_prs(&server, &[pr_json(1), custom_bot, human]).await;
+        let octo = mock_octocrab(&server);
+        let numbers = |prs: Vec<DependabotPr>| prs.iter().map(|pr| pr.number).collect::<Vec<_>>();
+
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let prs = dependabot_prs_passing_checks(
+            &octo,
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut RepoReport::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(prs), vec![1]);
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            bot_login = "renovate[bot]"
+            "#,
+        );
+        let prs = dependabot_prs_passing_checks(
+            &octo,
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut RepoReport::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(prs), vec![2]);
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            bot_login = ["dependabot[bot]", "renovate[bot]"]
+            "#,
+        );
+        let prs = dependabot_prs_passing_checks(
+            &octo,
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut RepoReport::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(prs), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_fetches_base_branch_once() {
+        let server = MockServer::start().await;
+        mount_prs(&server, &[pr_json(1), pr_json(2), pr_json(3)]).await;
+
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let mut report = RepoReport::new("org/repo");
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(prs.len(), 3);
+        assert!(prs.iter().all(|pr| pr.rebased));
+
+        let ref_requests = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|req| req.url.path() == "/repos/org/repo/git/ref/heads/main")
+            .count();
+        assert_eq!(ref_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_detects_stale_branches() {
+        let server = MockServer::start().await;
+        // Mounted before `mount_prs`, so this one wins for the stale PR.
+        mount_comparison(&server, "head2", 3).await;
+        mount_prs(&server, &[pr_json(1), pr_json(2)]).await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/issues/2/comments"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+            .mount(&server)
+            .await;
+
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut RepoReport::new("org/repo"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            prs.iter()
+                .map(|pr| (pr.number, pr.rebased))
+                .collect::<Vec<_>>(),
+            vec![(1, true), (2, false)]
+        );
+
+        // Without the requirement the stale branch is mergeable as is.
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            require_up_to_date = false
+            "#,
+        );
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut RepoReport::new("org/repo"),
+        )
+        .await
+        .unwrap();
+        assert!(prs.iter().all(|pr| pr.rebased && !pr.needs_rebase()));
+    }
+
+    #[tokio::test]
+    async fn test_dependabot_prs_skips_drafts() {
+        let server = MockServer::start().await;
+        let mut draft = pr_json(2);
+        draft["draft"] = serde_json::json!(true);
+        mount_prs(&server, &[pr_json(1), draft]).await;
+
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+
+        let mut report = RepoReport::new("org/repo");
+
+        let prs = dependabot_prs_passing_checks(
+            &mock_octocrab(&server),
+            &cfg,
+            &cfg.repo_settings(&cfg.repos[0]),
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(prs.iter().map(|pr| pr.number).collect::<Vec<_>>(), vec![1]);
+        assert!(prs[0].all_checks_pass && prs[0].rebased);
+        assert_eq!(report.prs_found, 2);
+        assert_eq!(
+            report.events,
+            vec![PrEvent {
+                url: "https://github.com/org/repo/pull/2".to_string(),
+                action: Action::Skipped,
+                reason: Some("draft".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_has_comment() {
+        let since = "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let comment = |body: &str, created_at: &str| IssueComment {
+            body: Some(body.to_string()),
+            created_at: created_at.parse().unwrap(),
+            user: None,
+        };
+
+        let commands = ["@dependabot rebase", RECREATE_COMMAND];
+        assert!(!has_comment(&[], since, &commands));
+        assert!(has_comment(
+            &[comment("@dependabot rebase", "2024-05-01T12:30:00Z")],
+            since,
+            &commands
+        ));
+        assert!(has_comment(
+            &[comment("@dependabot recreate\n", "2024-05-01T12:30:00Z")],
+            since,
+            &commands
+        ));
+        assert!(!has_comment(
+            &[comment("@dependabot rebase", "2024-05-01T11:30:00Z")],
+            since,
+            &commands
+        ));
+        assert!(!has_comment(
+            &[comment("Looks good", "2024-05-01T12:30:00Z")],
+            since,
+            &commands
+        ));
+    }
+
+    /// Serves canned PRs and records every change it's asked to make.
+    #[derive(Default)]
+    struct MockGitHub {
+        prs: Vec<DependabotPr>,
+        comments: Vec<IssueComment>,
+        reviews: Vec<Review>,
+        merge_queue: MergeQueueState,
+        auto_merge_fails: bool,
+        /// This many comments fail with a secondary rate limit error.
+        comments_rate_limited: std::cell::Cell<usize>,
+        owner_repos: Vec<RepoInfo>,
+        merge_checks_pass: Option<bool>,
+        /// The token has no write permission.
+        read_only: bool,
+        calls: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl MockGitHub {
+        fn with_prs(prs: Vec<DependabotPr>) -> Self {
+            Self {
+                prs,
+                ..Self::default()
+            }
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.borrow().clone()
+        }
+    }
+
+    impl GitHubApi for MockGitHub {
+        async fn dependabot_prs(
+            &self,
+            _cfg: &Config,
+            _settings: &RepoSettings,
+            report: &mut RepoReport,
+        ) -> Result<Vec<DependabotPr>, AutomergeError> {
+            report.prs_found += self.prs.len();
+            report.read_only = self.read_only;
+            Ok(self.prs.clone())
+        }
+
+        async fn owner_repos(&self, owner: &str) -> octocrab::Result<Vec<RepoInfo>> {
+            let prefix = format!("{}/", owner);
+            Ok(self
+                .owner_repos
+                .iter()
+                .filter(|repo| repo.full_name.starts_with(&prefix))
+                .cloned()
+                .collect())
+        }
+
+        async fn comments_since(
+            &self,
+            _pr: &DependabotPr,
+            _since: DateTime<Utc>,
+        ) -> octocrab::Result<Vec<IssueComment>> {
+            Ok(self.comments.clone())
+        }
+
+        async fn comment(&self, pr: &DependabotPr, body: &str) -> octocrab::Result<()> {
+            self.calls
+                .borrow_mut()
+                .push(format!("comment {} {}", pr.number, body));
+            let limited = self.comments_rate_limited.get();
+            if limited > 0 {
+                self.comments_rate_limited.set(limited - 1);
+                return Err(secondary_rate_limit_error().await);
+            }
+            Ok(())
+        }
+
+        async fn viewer(&self) -> octocrab::Result<String> {
+            Ok("me".to_string())
+        }
+
+        async fn reviews(&self, _pr: &DependabotPr) -> octocrab::Result<Vec<Review>> {
+            Ok(self.reviews.clone())
+        }
+
+        async fn approve(&self, pr: &DependabotPr) -> octocrab::Result<()> {
+            self.calls
+                .borrow_mut()
+                .push(format!("approve {}", pr.number));
+            Ok(())
+        }
+
+        async fn merge(
+            &self,
+            pr: &DependabotPr,
+            _merge_method: Option<MergeMethod>,
+            _commit: &MergeCommit,
+        ) -> octocrab::Result<MergeResult> {
+            self.calls.borrow_mut().push(format!("merge {}", pr.number));
+            Ok(MergeResult {
+                sha: format!("merge{}", pr.number),
+            })
+        }
+
+        async fn delete_branch(&self, pr: &DependabotPr) -> octocrab::Result<()> {
+            self.calls
+                .borrow_mut()
+                .push(format!("delete {}", pr.head_ref));
+            Ok(())
+        }
+
+        async fn close(&self, pr: &DependabotPr) -> octocrab::Result<()> {
+            self.calls.borrow_mut().push(format!("close {}", pr.number));
+            Ok(())
+        }
+
+        async fn commit_checks_pass(
+            &self,
+            _repo: &str,
+            _sha: &str,
+            _blocking_conclusions: &[String],
+        ) -> octocrab::Result<Option<bool>> {
+            Ok(self.merge_checks_pass)
+        }
+
+        async fn merge_queue_state(
+            &self,
+            _pr: &DependabotPr,
+        ) -> Result<MergeQueueState, AutomergeError> {
+            Ok(self.merge_queue.clone())
+        }
+
+        async fn enqueue(&self, pr: &DependabotPr) -> Result<(), AutomergeError> {
+            self.calls
+                .borrow_mut()
+                .push(format!("enqueue {}", pr.number));
+            Ok(())
+        }
+
+        async fn enable_auto_merge(
+            &self,
+            pr: &DependabotPr,
+            _merge_method: Option<MergeMethod>,
+        ) -> Result<(), AutomergeError> {
+            self.calls
+                .borrow_mut()
+                .push(format!("auto-merge {}", pr.number));
+            if self.auto_merge_fails {
+                return Err(AutomergeError::GraphQl(
+                    "auto-merge is not allowed for this repository".to_string(),
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    async fn secondary_rate_limit_error() -> octocrab::Error {
+        let body = r#"{"message": "You have exceeded a secondary rate limit."}"#;
+        let response = http::Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(BoxBody::new(
+                Full::new(Bytes::from(body)).map_err(|e| match e {}),
+            ))
+            .unwrap();
+        octocrab::map_github_error(response).await.unwrap_err()
+    }
+
+    async fn check_mock_prs(github: &MockGitHub, cfg: &Config, dry_run: bool) -> RepoReport {
+        let ctx = RunContext::new(github, None, cfg, dry_run);
+        let mut report = RepoReport::new("org/repo");
+        check_prs(&ctx, &cfg.repo_settings(&cfg.repos[0]), &mut report)
+            .await
+            .unwrap();
+        report
+    }
+
+    fn actions(report: &RepoReport) -> Vec<(u64, Action)> {
+        report
+            .events
+            .iter()
+            .map(|event| {
+                let number = event.url.rsplit('/').next().unwrap().parse().unwrap();
+                (number, event.action)
+            })
+            .collect()
+    }
+
+    fn stale_pr(number: u64) -> DependabotPr {
+        DependabotPr {
+            rebased: false,
+            ..dependabot_pr(number)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_merges_one_and_rebases_the_next() {
+        let github = MockGitHub::with_prs(vec![stale_pr(1), dependabot_pr(2), dependabot_pr(3)]);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec!["approve 2", "merge 2", "comment 1 @dependabot rebase"]
+        );
+        assert_eq!(
+            actions(&report),
+            vec![
+                (2, Action::Approved),
+                (2, Action::Merged),
+                (1, Action::Rebased)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_waits_while_a_rebase_is_in_progress() {
+        let rebasing = DependabotPr {
+            rebase_in_progress: true,
+            ..stale_pr(1)
+        };
+        let github = MockGitHub::with_prs(vec![rebasing, dependabot_pr(2)]);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert!(github.calls().is_empty());
+        assert_eq!(
+            actions(&report),
+            vec![(1, Action::Skipped), (2, Action::Skipped)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_comments_skip_reasons_once() {
+        let prerelease = DependabotPr {
+            new_version: "2.0.0-rc.1".to_string(),
+            ..dependabot_pr(1)
+        };
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            comment_skip_reasons = true
+            "#,
+        );
+        let github = MockGitHub::with_prs(vec![prerelease.clone()]);
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec!["comment 1 Not auto-merged: pre-release version."]
+        );
+
+        let github = MockGitHub {
+            comments: vec![IssueComment {
+                body: Some("Not auto-merged: pre-release version.".to_string()),
+                created_at: Utc::now(),
+                user: None,
+            }],
+            ..MockGitHub::with_prs(vec![prerelease])
+        };
+        check_mock_prs(&github, &cfg, false).await;
+        assert!(github.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_unparsed_versions() {
+        let action_pr = |number: u64, title: &str| {
+            let (old_version, new_version) = parse_version_range(title);
+            DependabotPr {
+                dependency_name: parse_dependency_from_pr(title).unwrap(),
+                old_version: old_version.unwrap_or_default(),
+                new_version: new_version.unwrap_or_default(),
+                ..dependabot_pr(number)
+            }
+        };
+        let prs = vec![
+            action_pr(1, "Bump actions/checkout from 3 to 4"),
+            action_pr(2, "Bump actions/setup-node from v3 to v4"),
+            action_pr(3, "Bump some/action from v1 to main"),
+        ];
+        let skipped = |report: &RepoReport| -> Vec<(u64, Option<String>)> {
+            report
+                .events
+                .iter()
+                .filter(|event| event.action == Action::Skipped)
+                .map(|event| {
+                    (
+                        event.url.rsplit('/').next().unwrap().parse().unwrap(),
+                        event.reason.clone(),
+                    )
+                })
+                .collect()
+        };
+
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let github = MockGitHub::with_prs(prs.clone());
+        let report = check_mock_prs(&github, &cfg, true).await;
+        assert_eq!(
+            skipped(&report),
+            vec![(3, Some("new version couldn't be parsed".to_string()))]
+        );
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            unparsed_versions = "merge"
+            "#,
+        );
+        let github = MockGitHub::with_prs(vec![prs[2].clone()]);
+        let report = check_mock_prs(&github, &cfg, true).await;
+        assert_eq!(
+            actions(&report),
+            vec![(3, Action::Approved), (3, Action::Merged)]
+        );
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            unparsed_versions = "require_manual"
+            "#,
+        );
+        let github = MockGitHub::with_prs(vec![prs[0].clone(), prs[2].clone()]);
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec![
+                "comment 3 Not auto-merged: new version couldn't be parsed.",
+                "approve 1",
+                "merge 1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_closes_stale_prs() {
+        let long_ago = Some(Utc::now() - chrono::Duration::days(60));
+        let failing = DependabotPr {
+            all_checks_pass: false,
+            created_at: long_ago,
+            ..dependabot_pr(1)
+        };
+        let on_hold = DependabotPr {
+            labels: vec!["on-hold".to_string()],
+            all_checks_pass: false,
+            created_at: long_ago,
+            ..dependabot_pr(2)
+        };
+        let recent = DependabotPr {
+            all_checks_pass: false,
+            ..dependabot_pr(3)
+        };
+        let passing = DependabotPr {
+            created_at: long_ago,
+            ..dependabot_pr(4)
+        };
+        let prs = vec![failing, on_hold, recent, passing];
+
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let github = MockGitHub::with_prs(prs.clone());
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert!(!github.calls().iter().any(|call| call.starts_with("close")));
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("on hold (on-hold)")
+        );
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            close_stale_after_days = 30
+            "#,
+        );
+        let github = MockGitHub::with_prs(prs);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec![
+                "comment 1 Closed by automerge: open for more than 30 days and can't be merged (checks failing).",
+                "close 1",
+                "approve 4",
+                "merge 4",
+            ]
+        );
+        assert!(actions(&report).contains(&(1, Action::Closed)));
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_keeps_stale_prs_held_by_config_open() {
+        let long_ago = Some(Utc::now() - chrono::Duration::days(60));
+        let major = DependabotPr {
+            new_version: "2.0.0".to_string(),
+            all_checks_pass: false,
+            created_at: long_ago,
+            ..dependabot_pr(1)
+        };
+        let conflicting = DependabotPr {
+            mergeable: Some(false),
+            recreate_requested: true,
+            labels: vec!["automerge".to_string()],
+            created_at: long_ago,
+            ..dependabot_pr(2)
+        };
+        let prs = vec![major, conflicting];
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            close_stale_after_days = 30
+            max_bump = "minor"
+            "#,
+        );
+        let github = MockGitHub::with_prs(prs.clone());
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec![
+                "comment 2 Closed by automerge: open for more than 30 days and can't be merged (needs manual conflict resolution).",
+                "close 2",
+            ]
+        );
+        assert_eq!(
+            actions(&report),
+            vec![
+                (1, Action::Skipped),
+                (2, Action::Skipped),
+                (2, Action::Closed)
+            ]
+        );
+        assert_eq!(report.events[0].reason.as_deref(), Some("Major bump"));
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            close_stale_after_days = 30
+            require_labels = ["automerge"]
+            "#,
+        );
+        let github = MockGitHub::with_prs(prs);
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["comment 2 Closed by automerge: open for more than 30 days and can't be merged (needs manual conflict resolution).", "close 2"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_approve_only() {
+        let github = MockGitHub::with_prs(vec![dependabot_pr(1), dependabot_pr(2), stale_pr(3)]);
+        let cfg = test_config(r#"repos = [{ name = "org/repo", action = "approve_only" }]"#);
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec!["approve 1", "approve 2", "comment 3 @dependabot rebase"]
+        );
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            action = "rebase_only"
+            "#,
+        );
+        let github = MockGitHub::with_prs(vec![dependabot_pr(1), stale_pr(3)]);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["comment 3 @dependabot rebase"]);
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("action is rebase_only")
+        );
+
+        // Merging takes write permission.
+        let github = MockGitHub {
+            read_only: true,
+            ..MockGitHub::with_prs(vec![
+                dependabot_pr(1),
+                stale_pr(3),
+                DependabotPr {
+                    all_checks_pass: false,
+                    created_at: Some(Utc::now() - chrono::Duration::days(60)),
+                    ..dependabot_pr(4)
+                },
+            ])
+        };
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            close_stale_after_days = 1
+            "#,
+        );
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec!["approve 1", "comment 3 @dependabot rebase"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_recreates_conflicting_prs() {
+        let conflicting = DependabotPr {
+            mergeable: Some(false),
+            ..dependabot_pr(1)
+        };
+        let github = MockGitHub::with_prs(vec![conflicting.clone()]);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["comment 1 @dependabot recreate"]);
+        assert_eq!(
+            actions(&report),
+            vec![(1, Action::Skipped), (1, Action::Rebased)]
+        );
+
+        let recreated = DependabotPr {
+            recreate_requested: true,
+            ..conflicting.clone()
+        };
+        let github = MockGitHub::with_prs(vec![recreated]);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert!(github.calls().is_empty());
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("needs manual conflict resolution")
+        );
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            recreate_conflicting = false
+            "#,
+        );
+        let github = MockGitHub::with_prs(vec![conflicting]);
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["comment 1 @dependabot rebase"]);
+    }
+
+    #[test]
+    fn test_is_security_update() {
+        assert!(is_security_update(
+            &["Security".to_string()],
+            "Bumps foo from 1.0.0 to 1.0.1."
+        ));
+        assert!(is_security_update(
+            &[],
+            "Bumps foo to 1.0.1 to fix a Security Vulnerability."
+        ));
+        assert!(!is_security_update(&[], "Bumps foo from 1.0.0 to 1.0.1."));
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_merges_security_updates_first() {
+        let security = DependabotPr {
+            is_security: true,
+            ..dependabot_pr(2)
+        };
+        let github = MockGitHub::with_prs(vec![dependabot_pr(1), security]);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["approve 2", "merge 2"]);
+
+        let cfg = test_config("repos = [\"org/repo\"]\nsecurity_updates_first = false");
+        let github = MockGitHub::with_prs(github.prs.clone());
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["approve 1", "merge 1"]);
+    }
+
+    #[test]
+    fn test_sort_prs() {
+        let pr = |number, created_at: &str, new_version: &str| DependabotPr {
+            created_at: Some(created_at.parse().unwrap()),
+            new_version: new_version.to_string(),
+            ..dependabot_pr(number)
+        };
+        let mut prs = vec![
+            pr(1, "2024-05-02T00:00:00Z", "2.0.0"),
+            pr(2, "2024-05-01T00:00:00Z", "1.1.0"),
+            pr(3, "2024-05-03T00:00:00Z", "1.0.1"),
+            pr(4, "2024-05-04T00:00:00Z", "abcdef1"),
+        ];
+        let numbers = |prs: &[DependabotPr]| prs.iter().map(|pr| pr.number).collect::<Vec<_>>();
+
+        sort_prs(&mut prs, MergeOrder::OldestFirst);
+        assert_eq!(numbers(&prs), vec![2, 1, 3, 4]);
+        sort_prs(&mut prs, MergeOrder::NewestFirst);
+        assert_eq!(numbers(&prs), vec![4, 3, 1, 2]);
+        sort_prs(&mut prs, MergeOrder::SmallestBumpFirst);
+        assert_eq!(numbers(&prs), vec![3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn test_decisions() {
+        let failing = DependabotPr {
+            all_checks_pass: false,
+            ..dependabot_pr(2)
+        };
+        let prs = vec![
+            dependabot_pr(1),
+            failing,
+            dependabot_pr(3),
+            dependabot_pr(4),
+        ];
+        let mut report = RepoReport::new("org/repo");
+        report.skip("https://github.com/org/repo/pull/5", "draft");
+        report.record(&prs[0].url, Action::Approved, None);
+        report.record(&prs[0].url, Action::Merged, None);
+        report.skip(&prs[3].url, "pre-release version");
+        assert_eq!(
+            decisions(&prs, &report)
+                .iter()
+                .map(|decision| decision.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "skip #5: draft",
+                "merged #1",
+                "skip #4: pre-release version",
+                "skip #2: checks failing",
+                "eligible #3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_state() {
+        let now = Utc::now();
+        let pr = DependabotPr {
+            mergeable: Some(false),
+            ..dependabot_pr(1)
+        };
+        let mut state = State::default();
+        assert_eq!(state.rebase_requests(&pr), 0);
+        assert!(!state.rebase_requested_since(&pr, now));
+
+        state.record(&pr, Action::Rebased, now);
+        state.record(&pr, Action::Rebased, now);
+        assert_eq!(state.rebase_requests(&pr), 2);
+        assert!(state.rebase_requested_since(&pr, now));
+        assert!(state.escalate(&pr));
+        assert!(!state.escalate(&pr));
+
+        // Dependabot pushed, so it's not stuck anymore.
+        let pushed = DependabotPr {
+            head_sha: "rebased".to_string(),
+            ..pr.clone()
+        };
+        assert_eq!(state.rebase_requests(&pushed), 0);
+        state.record(&pushed, Action::Rebased, now);
+        assert_eq!(state.rebase_requests(&pushed), 1);
+        assert!(state.escalate(&pushed));
+
+        state.record(&dependabot_pr(2), Action::Merged, now);
+        state.retain_open("org/repo", &[dependabot_pr(2)]);
+        assert_eq!(state.prs.keys().collect::<Vec<_>>(), vec!["org/repo#2"]);
+
+        let path =
+            std::env::temp_dir().join(format!("automerge-state-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(State::load(path), State::default());
+        state.save(path).unwrap();
+        assert_eq!(State::load(path), state);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_state_quarantine() {
+        let now = Utc::now();
+        let mut state = State::default();
+        state.record_merge(&dependabot_pr(1), now);
+        assert!(state.merges.is_empty());
+
+        let merged = DependabotPr {
+            merge_sha: Some("m1".to_string()),
+            ..dependabot_pr(1)
+        };
+        state.record_merge(&merged, now);
+        assert_eq!(state.pending_merges("org/repo").len(), 1);
+        assert!(state.pending_merges("org/other").is_empty());
+
+        let until = now + chrono::Duration::hours(6);
+        state.resolve_merge("m1", Some(until));
+        assert!(state.merges.is_empty());
+        assert_eq!(
+            state.quarantined_until("org/repo", "dep1", now),
+            Some(until)
+        );
+        assert_eq!(state.quarantined_until("org/other", "dep1", now), None);
+        assert_eq!(state.quarantined_until("org/repo", "dep1", until), None);
+
+        state.expire_quarantines(until);
+        assert!(state.quarantined.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_quarantines_dependencies_that_broke_main() {
+        let github = MockGitHub {
+            merge_checks_pass: Some(false),
+            ..MockGitHub::with_prs(vec![dependabot_pr(1), dependabot_pr(2)])
+        };
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            quarantine_hours = 6
+            state_file = "unused"
+            "#,
+        );
+        let mut state = State::default();
+        let merged = DependabotPr {
+            merge_sha: Some("m1".to_string()),
+            ..dependabot_pr(1)
+        };
+        state.record_merge(&merged, Utc::now());
+
+        let ctx = RunContext::new(&github, None, &cfg, true).with_state(state);
+        let settings = cfg.repo_settings(&cfg.repos[0]);
+        let mut report = RepoReport::new("org/repo");
+        check_prs(&ctx, &settings, &mut report).await.unwrap();
+        let skipped = report.events.iter().find(|event| event.url.ends_with("/1"));
+        assert!(skipped
+            .and_then(|event| event.reason.as_deref())
+            .unwrap()
+            .starts_with("dep1 is quarantined until "));
+        assert!(actions(&report).contains(&(2, Action::Merged)));
+        assert_eq!(ctx.state(|state| state.merges.len()), Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_leaves_stuck_prs_to_humans() {
+        let conflicting = stale_pr(1);
+        let github = MockGitHub::with_prs(vec![conflicting.clone()]);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let long_ago = Utc::now() - chrono::Duration::days(1);
+        let mut state = State::default();
+        state.record(&conflicting, Action::Rebased, long_ago);
+
+        let ctx = RunContext::new(&github, None, &cfg, false).with_state(state);
+        let settings = cfg.repo_settings(&cfg.repos[0]);
+        let mut report = RepoReport::new("org/repo");
+        check_prs(&ctx, &settings, &mut report).await.unwrap();
+        assert_eq!(github.calls(), vec!["comment 1 @dependabot rebase"]);
+
+        // Too soon to ask again.
+        let mut report = RepoReport::new("org/repo");
+        check_prs(&ctx, &settings, &mut report).await.unwrap();
+        assert_eq!(github.calls().len(), 1);
+        assert_eq!(
+            report.events.last().unwrap().reason.as_deref(),
+            Some("rebase already requested, waiting for dependabot")
+        );
+
+        ctx.state(|state| state.prs.get_mut("org/repo#1").unwrap().at = long_ago);
+        let mut report = RepoReport::new("org/repo");
+        check_prs(&ctx, &settings, &mut report).await.unwrap();
+        assert_eq!(github.calls().len(), 2);
+
+        ctx.state(|state| state.prs.get_mut("org/repo#1").unwrap().at = long_ago);
+        let mut report = RepoReport::new("org/repo");
+        check_prs(&ctx, &settings, &mut report).await.unwrap();
+        assert_eq!(github.calls().len(), 2);
+        assert_eq!(
+            report.events.last().unwrap().reason.as_deref(),
+            Some("dependabot didn't rebase it after 3 requests")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_skips_paused_repos() {
+        let github = MockGitHub::with_prs(vec![dependabot_pr(1)]);
+        let cfg = test_config(
+            r#"
+            repos = [{ name = "org/repo", paused = true }]
+            "#,
+        );
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert!(report.paused);
+        assert!(github.calls().is_empty());
+
+        let cfg = test_config(
+            r#"
+            paused = true
+            repos = ["org/repo"]
+            "#,
+        );
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert!(report.paused);
+        assert!(github.calls().is_empty());
+        let run = RunReport {
+            repos: vec![report],
+            ..RunReport::default()
+        };
+        assert_eq!(run.outcome(), RunOutcome::Paused);
+    }
+
+    #[tokio::test]
+    async fn test_run_once_sends_no_requests_when_paused() {
+        let server = MockServer::start().await;
+        let cfg = test_config(
+            r#"
+            paused = true
+            repos = ["org/*"]
+            "#,
+        );
+        let report = run_once(&mock_octocrab(&server), None, &cfg, false, None).await;
+        assert!(server.received_requests().await.unwrap().is_empty());
+        assert_eq!(report.outcome(), RunOutcome::Paused);
+    }
+
+    #[tokio::test]
+    async fn test_error_kinds() {
+        let err = AutomergeError::from(secondary_rate_limit_error().await);
+        assert_eq!(err.status(), Some(StatusCode::FORBIDDEN));
+        assert!(err.is_rate_limited());
+        assert!(!err.is_unauthorized());
+        assert!(AutomergeError::Auth("Bad credentials".to_string()).is_unauthorized());
+
+        let err = AutomergeError::Config("invalid repo name".to_string());
+        assert_eq!(err.status(), None);
+        assert!(!err.is_rate_limited());
+        assert_eq!(err.to_string(), "invalid config: invalid repo name");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_check_prs_retries_secondary_rate_limited_rebase() {
+        let conflicting = DependabotPr {
+            mergeable: Some(false),
+            ..dependabot_pr(1)
+        };
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+
+        let github = MockGitHub {
+            comments_rate_limited: 1.into(),
+            ..MockGitHub::with_prs(vec![conflicting.clone()])
+        };
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls().len(), 2);
+        assert_eq!(
+            actions(&report),
+            vec![(1, Action::Skipped), (1, Action::Rebased)]
+        );
+
+        let github = MockGitHub {
+            comments_rate_limited: 2.into(),
+            ..MockGitHub::with_prs(vec![conflicting])
+        };
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls().len(), 2);
+        assert_eq!(
+            report.events[1].reason.as_deref(),
+            Some("secondary rate limited")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_does_not_repeat_rebase_requests() {
+        let github = MockGitHub {
+            comments: vec![IssueComment {
+                body: Some("@dependabot rebase".to_string()),
+                created_at: Utc::now(),
+                user: None,
+            }],
+            ..MockGitHub::with_prs(vec![stale_pr(1)])
+        };
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert!(github.calls().is_empty());
+        assert_eq!(actions(&report), vec![(1, Action::Skipped)]);
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_dry_run_changes_nothing() {
+        let github = MockGitHub::with_prs(vec![stale_pr(1), dependabot_pr(2)]);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let report = check_mock_prs(&github, &cfg, true).await;
+        assert!(github.calls().is_empty());
+        assert_eq!(
+            actions(&report),
+            vec![
+                (2, Action::Approved),
+                (2, Action::Merged),
+                (1, Action::Rebased)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_approves_blocked_prs_without_merging() {
+        let blocked = DependabotPr {
+            mergeable_state: Some(MergeableState::Blocked),
+            ..dependabot_pr(1)
+        };
+        let github = MockGitHub::with_prs(vec![blocked, stale_pr(2)]);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec!["approve 1", "comment 2 @dependabot rebase"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_skips_existing_approval() {
+        let github = MockGitHub {
+            reviews: vec![Review {
+                user: Some(User {
+                    login: "me".to_string(),
+                }),
+                state: "APPROVED".to_string(),
+                commit_id: Some("head1".to_string()),
+            }],
+            ..MockGitHub::with_prs(vec![dependabot_pr(1)])
+        };
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            delete_branch_after_merge = true
+            "#,
+        );
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["merge 1", "delete dependabot/1"]);
+    }
+
+    #[test]
+    fn test_merge_window_contains() {
+        let window: MergeWindow = toml::from_str(
+            r#"
+            timezone = "Europe/Vilnius"
+            days = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+            start_hour = 9
+            end_hour = 17
+            "#,
+        )
+        .unwrap();
+        let at = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
+        // Friday, 09:30 in Vilnius.
+        assert!(window.contains(at("2024-05-03T06:30:00Z")));
+        // Friday, 17:30 in Vilnius.
+        assert!(!window.contains(at("2024-05-03T14:30:00Z")));
+        // Saturday, 12:00 in Vilnius.
+        assert!(!window.contains(at("2024-05-04T09:00:00Z")));
+
+        let overnight: MergeWindow = toml::from_str("start_hour = 22\nend_hour = 6").unwrap();
+        assert!(overnight.contains(at("2024-05-03T23:00:00Z")));
+        assert!(overnight.contains(at("2024-05-04T05:59:00Z")));
+        assert!(!overnight.contains(at("2024-05-04T12:00:00Z")));
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_outside_merge_window() {
+        let prs = vec![dependabot_pr(1), stale_pr(2)];
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            merge_window = { days = [] }
+            "#,
+        );
+        let github = MockGitHub::with_prs(prs.clone());
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["comment 2 @dependabot rebase"]);
+        assert_eq!(
+            actions(&report),
+            vec![(1, Action::Skipped), (2, Action::Rebased)]
+        );
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            merge_window = { days = [], rebase_outside = false }
+            "#,
+        );
+        let github = MockGitHub::with_prs(prs);
+        check_mock_prs(&github, &cfg, false).await;
+        assert!(github.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_uses_merge_queue() {
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            use_merge_queue = true
+            "#,
+        );
+
+        let github = MockGitHub {
+            merge_queue: MergeQueueState {
+                is_merge_queue_enabled: true,
+                ..MergeQueueState::default()
+            },
+            ..MockGitHub::with_prs(vec![dependabot_pr(1)])
+        };
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["approve 1", "enqueue 1"]);
+        assert_eq!(
+            actions(&report),
+            vec![(1, Action::Approved), (1, Action::Queued)]
+        );
+
+        let github = MockGitHub {
+            merge_queue: MergeQueueState {
+                is_merge_queue_enabled: true,
+                is_in_merge_queue: true,
+                ..MergeQueueState::default()
+            },
+            ..MockGitHub::with_prs(vec![dependabot_pr(1)])
+        };
+        check_mock_prs(&github, &cfg, false).await;
+        assert!(github.calls().is_empty());
+
+        // No queue on the base branch, merged directly.
+        let github = MockGitHub::with_prs(vec![dependabot_pr(1)]);
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["approve 1", "merge 1"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_enables_auto_merge() {
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            enable_auto_merge = true
+            "#,
+        );
+
+        let blocked = DependabotPr {
+            mergeable_state: Some(MergeableState::Blocked),
+            ..dependabot_pr(1)
+        };
+        let github = MockGitHub::with_prs(vec![blocked]);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["approve 1", "auto-merge 1"]);
+        assert_eq!(
+            actions(&report),
+            vec![(1, Action::Approved), (1, Action::AutoMerge)]
+        );
+
+        let github = MockGitHub {
+            auto_merge_fails: true,
+            ..MockGitHub::with_prs(vec![dependabot_pr(1)])
+        };
+        check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(github.calls(), vec!["approve 1", "auto-merge 1", "merge 1"]);
+
+        let github = MockGitHub {
+            merge_queue: MergeQueueState {
+                auto_merge_request: Some(serde_json::json!({})),
+                ..MergeQueueState::default()
+            },
+            ..MockGitHub::with_prs(vec![dependabot_pr(1)])
+        };
+        check_mock_prs(&github, &cfg, false).await;
+        assert!(github.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_merges_via_comment() {
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            merge_via_comment = true
+            merge_method = "squash"
+            "#,
+        );
+        let github = MockGitHub::with_prs(vec![dependabot_pr(1)]);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            github.calls(),
+            vec!["approve 1", "comment 1 @dependabot squash and merge"]
+        );
+        assert_eq!(
+            actions(&report),
+            vec![(1, Action::Approved), (1, Action::AutoMerge)]
+        );
+
+        let github = MockGitHub {
+            comments: vec![IssueComment {
+                body: Some("@dependabot squash and merge".to_string()),
+                created_at: Utc::now(),
+                user: None,
+            }],
+            ..MockGitHub::with_prs(vec![dependabot_pr(1)])
+        };
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert!(github.calls().is_empty());
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("merge already requested, waiting for dependabot")
+        );
+        assert_eq!(merge_command(None), "@dependabot merge");
+    }
+
+    fn dependabot_pr(number: u64) -> DependabotPr {
+        DependabotPr {
+            url: format!("https://github.com/org/repo/pull/{}", number),
+            number,
+            repo: Repo {
+                org: "org".to_string(),
+                repo: "repo".to_string(),
+            },
+            node_id: format!("PR_{}", number),
+            head_ref: format!("dependabot/{}", number),
+            head_sha: format!("head{}", number),
+            all_checks_pass: true,
+            rebased: true,
+            rebase_in_progress: false,
+            rebase_failed: false,
+            failed_rebases: 0,
+            recreate_requested: false,
+            draft: false,
+            created_at: None,
+            mergeable: Some(true),
+            mergeable_state: Some(MergeableState::Clean),
+            labels: vec![],
+            is_security: false,
+            dependency_name: format!("dep{}", number),
+            old_version: "1.0.0".to_string(),
+            new_version: "1.0.1".to_string(),
+            is_group: false,
+            group_updates: vec![],
+            merge_sha: None,
+        }
+    }
+
+    #[test]
+    fn test_rebase_command() {
+        let failed = |failed_rebases, recreate_requested| DependabotPr {
+            rebase_failed: true,
+            failed_rebases,
+            recreate_requested,
+            ..dependabot_pr(1)
+        };
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        assert_eq!(
+            rebase_command(&dependabot_pr(1), &cfg),
+            Some("@dependabot rebase")
+        );
+        assert_eq!(rebase_command(&failed(1, false), &cfg), None);
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            rebase_command = "@dependabot rebase\n\nRequested by automerge."
+            recreate_after_failed_rebases = 2
+            "#,
+        );
+        assert_eq!(
+            rebase_command(&failed(1, false), &cfg),
+            Some("@dependabot rebase\n\nRequested by automerge.")
+        );
+        assert_eq!(
+            rebase_command(&failed(2, false), &cfg),
+            Some("@dependabot recreate")
+        );
+        assert_eq!(rebase_command(&failed(3, true), &cfg), None);
+    }
+
+    #[test]
+    fn test_rebase_command_recreates_conflicting() {
+        let conflicting = |recreate_requested| DependabotPr {
+            mergeable_state: Some(MergeableState::Dirty),
+            recreate_requested,
+            ..dependabot_pr(1)
+        };
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        assert_eq!(
+            rebase_command(&conflicting(false), &cfg),
+            Some("@dependabot recreate")
+        );
+        assert_eq!(rebase_command(&conflicting(true), &cfg), None);
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            recreate_conflicting = false
+            "#,
+        );
+        assert_eq!(
+            rebase_command(&conflicting(true), &cfg),
+            Some("@dependabot rebase")
+        );
+    }
+
+    #[test]
+    fn test_rebase_failed() {
+        let bot = vec!["dependabot[bot]".to_string()];
+        let comment = |login: &str, body: &str, created_at: &str| IssueComment {
+            body: Some(body.to_string()),
+            created_at: created_at.parse().unwrap(),
+            user: Some(User {
+                login: login.to_string(),
+            }),
+        };
+        let request = comment("me", "@dependabot rebase", "2024-05-01T12:00:00Z");
+        let failure = comment(
+            "dependabot[bot]",
+            "Looks like this PR has merge conflicts that Dependabot can't resolve.",
+            "2024-05-01T12:05:00Z",
+        );
+
+        assert!(!rebase_failed(&[], &bot));
+        assert!(!rebase_failed(std::slice::from_ref(&request), &bot));
+        assert!(rebase_failed(&[request, failure.clone()], &bot));
+        assert!(!rebase_failed(
+            &[
+                failure.clone(),
+                comment("me", "@dependabot recreate", "2024-05-01T13:00:00Z")
+            ],
+            &bot
+        ));
+        assert!(!rebase_failed(
+            &[comment(
+                "me",
+                "Dependabot can't rebase this",
+                "2024-05-01T12:05:00Z"
+            )],
+            &bot
+        ));
+    }
+
+    #[test]
+    fn test_needs_rebase() {
+        let pr = dependabot_pr(1);
+        assert!(!pr.has_conflicts() && !pr.needs_rebase());
+
+        let stale = DependabotPr {
+            rebased: false,
+            ..dependabot_pr(1)
+        };
+        assert!(!stale.has_conflicts() && stale.needs_rebase());
+
+        let dirty = DependabotPr {
+            mergeable: Some(false),
+            mergeable_state: Some(MergeableState::Dirty),
+            ..dependabot_pr(1)
+        };
+        assert!(dirty.has_conflicts() && dirty.needs_rebase());
+
+        let unknown = DependabotPr {
+            mergeable: None,
+            mergeable_state: Some(MergeableState::Unknown),
+            ..dependabot_pr(1)
+        };
+        assert!(!unknown.has_conflicts());
+    }
+
+    fn no_retry() -> RetryConfig {
+        RetryConfig {
+            max_retries: 0,
+            base_delay_ms: 0,
+        }
+    }
+
+    fn test_config(toml: &str) -> Config {
+        let mut cfg: Config = toml::from_str(toml).unwrap();
+        cfg.retry = no_retry();
+        cfg
+    }
+
+    #[test]
+    fn test_is_old_enough() {
+        let now = "2024-05-02T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let created_at = "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().ok();
+        let hours = chrono::Duration::hours;
+
+        assert!(is_old_enough(created_at, hours(0), now));
+        assert!(is_old_enough(created_at, hours(24), now));
+        assert!(!is_old_enough(created_at, hours(25), now));
+        assert!(is_old_enough(None, hours(0), now));
+        assert!(!is_old_enough(None, hours(1), now));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let backoff = Duration::from_secs(2);
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+
+        assert_eq!(
+            retry_delay(
+                StatusCode::BAD_GATEWAY,
+                &HeaderMap::new(),
+                true,
+                backoff,
+                now
+            ),
+            Some(backoff)
+        );
+        assert_eq!(
+            retry_delay(
+                StatusCode::BAD_GATEWAY,
+                &HeaderMap::new(),
+                false,
+                backoff,
+                now
+            ),
+            None
+        );
+        assert_eq!(
+            retry_delay(StatusCode::NOT_FOUND, &HeaderMap::new(), true, backoff, now),
+            None
+        );
+        assert_eq!(
+            retry_delay(
+                StatusCode::TOO_MANY_REQUESTS,
+                &headers(&[("retry-after", "5")]),
+                false,
+                backoff,
+                now
+            ),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            retry_delay(
+                StatusCode::FORBIDDEN,
+                &headers(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1010")
+                ]),
+                false,
+                backoff,
+                now
+            ),
+            Some(Duration::from_secs(10))
+        );
+        // Plain permission errors aren't retried.
+        assert_eq!(
+            retry_delay(StatusCode::FORBIDDEN, &HeaderMap::new(), true, backoff, now),
+            None
+        );
+        // Too far in the future to wait for.
+        assert_eq!(
+            retry_delay(
+                StatusCode::FORBIDDEN,
+                &headers(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "5000")
+                ]),
+                true,
+                backoff,
+                now
+            ),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_recovers_from_5xx() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/1"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(pr_json(1)))
+            .mount(&server)
+            .await;
+
+        let octo = mock_octocrab(&server);
+        let retry = RetryConfig {
+            max_retries: 1,
+            base_delay_ms: 1,
+        };
+        let pr: octocrab::models::pulls::PullRequest =
+            send_with_retry(&retry, true, || octo._get("/repos/org/repo/pulls/1"))
+                .await
+                .unwrap();
+        assert_eq!(pr.number, 1);
+    }
+
+    #[tokio::test]
+    async fn test_comment_waits_for_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/issues/1/comments"))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .insert_header("Retry-After", "1")
+                    .set_body_json(serde_json::json!({
+                        "message": "You have exceeded a secondary rate limit.",
+                    })),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/issues/1/comments"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let octo = mock_octocrab(&server);
+        let retry = RetryConfig {
+            max_retries: 1,
+            base_delay_ms: 1,
+        };
+        let started = Instant::now();
+        GitHub::new(&octo, &retry)
+            .comment(&dependabot_pr(1), "@dependabot rebase")
+            .await
+            .unwrap();
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_secs(1), "{:?}", waited);
+        assert!(waited < SECONDARY_RATE_LIMIT_WAIT);
+    }
+
+    #[tokio::test]
+    async fn test_merge_is_not_retried_on_5xx() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/org/repo/pulls/1/merge"))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let octo = mock_octocrab(&server);
+        let retry = RetryConfig {
+            max_retries: 2,
+            base_delay_ms: 1,
+        };
+        let github = GitHub::new(&octo, &retry);
+        let merged = github
+            .merge(&dependabot_pr(1), None, &MergeCommit::default())
+            .await;
+        assert!(merged.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_recovers_from_timeouts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(pr_json(1))
+                    .set_delay(Duration::from_secs(5)),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(pr_json(1)))
+            .mount(&server)
+            .await;
+
+        let octo = build_octocrab(
+            &Credentials::Token("token".to_string()),
+            Some(&server.uri()),
+            Duration::from_millis(200),
+        )
+        .unwrap();
+        let retry = RetryConfig {
+            max_retries: 1,
+            base_delay_ms: 1,
+        };
+        let started = Instant::now();
+        let pr: octocrab::models::pulls::PullRequest =
+            send_with_retry(&retry, true, || octo._get("/repos/org/repo/pulls/1"))
+                .await
+                .unwrap();
+        assert_eq!(pr.number, 1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_maybe_merge_one_notifies_webhook() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/pulls/1/reviews"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/org/repo/pulls/1/merge"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(merge_json()))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/webhook"))
+            .and(body_partial_json(serde_json::json!({
+                "text": "Merged https://github.com/org/repo/pull/1 in org/repo as merge1",
+                "action": "merged",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let octo = mock_octocrab(&server);
+        let notifier =
+            Notifier::new(&format!("{}/webhook", server.uri()), Duration::from_secs(5)).unwrap();
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let github = GitHub {
+            octo: &octo,
+            retry: &cfg.retry,
+        };
+        let ctx = RunContext::new(&github, Some(&notifier), &cfg, false);
+        let mut report = RepoReport::new("org/repo");
+        let merged = maybe_merge_one(&ctx, &[dependabot_pr(1)], None, &mut report)
+            .await
+            .unwrap();
+        assert_eq!(
+            merged.map(|pr| (pr.number, pr.merge_sha)),
+            Some((1, Some("merge1".to_string())))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_waits_for_rebase_and_merges() {
+        let server = MockServer::start().await;
+        // Stale until dependabot has rebased it.
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/compare/base...head1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "behind_by": 1,
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        mount_prs(&server, &[pr_json(1)]).await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/issues/1/comments"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/issues/1/comments"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "id": 1,
+                "node_id": "node",
+                "url": "https://api.github.com/repos/org/repo/issues/comments/1",
+                "html_url": "https://github.com/org/repo/pull/1#issuecomment-1",
+                "body": "@dependabot rebase",
+                "user": author_json("me"),
+                "created_at": "2024-05-01T12:00:00Z",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/pulls/1/reviews"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/org/repo/pulls/1/merge"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(merge_json()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let octo = mock_octocrab(&server);
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            wait_for_rebase = true
+            "#,
+        );
+        let github = GitHub {
+            octo: &octo,
+            retry: &cfg.retry,
+        };
+        let ctx = RunContext::new(&github, None, &cfg, false);
+        let mut report = RepoReport::new("org/repo");
+        check_prs(&ctx, &cfg.repo_settings(&cfg.repos[0]), &mut report)
+            .await
+            .unwrap();
+        assert_eq!(
+            report
+                .events
+                .iter()
+                .map(|event| event.action)
+                .collect::<Vec<_>>(),
+            vec![Action::Rebased, Action::Approved, Action::Merged]
+        );
+    }
+
+    /// Runs `check_prs` end to end against a mock GitHub: a green rebased
+    /// PR gets approved and merged, a green stale one gets a rebase comment
+    /// and a failing one is filtered out without any writes.
+    #[tokio::test]
+    async fn test_check_prs_against_mock_github() {
+        let server = MockServer::start().await;
+        // Mounted before `mount_prs` so these win over its defaults.
+        mount_comparison(&server, "head2", 1).await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/commits/head3/check-runs"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "total_count": 1,
+                "check_runs": [{ "name": "build", "status": "completed", "conclusion": "failure" }],
+            })))
+            .mount(&server)
+            .await;
+        mount_prs(&server, &[pr_json(1), pr_json(2), pr_json(3)]).await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/repos/org/repo/issues/\d+/comments$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/issues/2/comments"))
+            .and(body_partial_json(
+                serde_json::json!({ "body": "@dependabot rebase" }),
+            ))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "id": 1,
+                "node_id": "node",
+                "url": "https://api.github.com/repos/org/repo/issues/comments/1",
+                "html_url": "https://github.com/org/repo/pull/2#issuecomment-1",
+                "body": "@dependabot rebase",
+                "user": author_json("me"),
+                "created_at": "2024-05-01T12:00:00Z",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/pulls/1/reviews"))
+            .and(body_partial_json(serde_json::json!({ "event": "APPROVE" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/org/repo/pulls/1/merge"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(merge_json()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // Anything else that writes is a bug.
+        for verb in ["POST", "PUT", "PATCH", "DELETE"] {
+            Mock::given(method(verb))
+                .and(path_regex(r"^/repos/"))
+                .respond_with(ResponseTemplate::new(500))
+                .expect(0)
+                .mount(&server)
+                .await;
+        }
+
+        let octo = mock_octocrab(&server);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let github = GitHub::new(&octo, &cfg.retry);
+        let ctx = RunContext::new(&github, None, &cfg, false);
+        let mut report = RepoReport::new("org/repo");
+        check_prs(&ctx, &cfg.repo_settings(&cfg.repos[0]), &mut report)
+            .await
+            .unwrap();
+
+        let mut events: Vec<(&str, Action)> = report
+            .events
+            .iter()
+            .map(|event| (event.url.rsplit('/').next().unwrap(), event.action))
+            .collect();
+        events.sort();
+        assert_eq!(
+            events,
+            vec![
+                ("1", Action::Approved),
+                ("1", Action::Merged),
+                ("2", Action::Rebased),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maybe_merge_one_respects_max_merges_per_run() {
+        let github = MockGitHub::default();
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            max_merges_per_run = 1
+            "#,
+        );
+        let ctx = RunContext::new(&github, None, &cfg, true);
+
+        let mut report = RepoReport::new("org/repo");
+        let merged = maybe_merge_one(&ctx, &[dependabot_pr(1)], None, &mut report)
+            .await
+            .unwrap();
+        assert_eq!(merged.map(|pr| pr.number), Some(1));
+
+        let mut report = RepoReport::new("org/other");
+        let merged = maybe_merge_one(&ctx, &[dependabot_pr(2)], None, &mut report)
+            .await
+            .unwrap();
+        assert!(merged.is_none());
+        assert_eq!(
+            report.events,
+            vec![PrEvent {
+                url: "https://github.com/org/repo/pull/2".to_string(),
+                action: Action::Skipped,
+                reason: Some("max_merges_per_run reached".to_string()),
+            }]
+        );
+
+        ctx.return_merge();
+        assert!(ctx.take_merge());
+        assert!(!ctx.take_merge());
+    }
+
+    #[test]
+    fn test_is_approved_by() {
+        let review = |login: &str, state: &str, commit_id: &str| Review {
+            user: Some(User {
+                login: login.to_string(),
+            }),
+            state: state.to_string(),
+            commit_id: Some(commit_id.to_string()),
+        };
+        assert!(!is_approved_by(&[], "me", "head1"));
+        assert!(is_approved_by(
+            &[review("me", "APPROVED", "head1")],
+            "me",
+            "head1"
+        ));
+        // Approvals of an older head don't count after a rebase.
+        assert!(!is_approved_by(
+            &[review("me", "APPROVED", "head0")],
+            "me",
+            "head1"
+        ));
+        assert!(!is_approved_by(
+            &[review("me", "COMMENTED", "head1")],
+            "me",
+            "head1"
+        ));
+        assert!(!is_approved_by(
+            &[review("someone", "APPROVED", "head1")],
+            "me",
+            "head1"
+        ));
+    }
+
+    #[test]
+    fn test_branch_protection_reason() {
+        assert_eq!(
+            branch_protection_reason(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "At least 1 approving review is required by reviewers with write access."
+            ),
+            Some(
+                "required reviews not met: At least 1 approving review is required by reviewers with write access."
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            branch_protection_reason(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Waiting on code owner review from org/team."
+            )
+            .as_deref(),
+            Some("required reviews not met: Waiting on code owner review from org/team.")
+        );
+        assert_eq!(
+            branch_protection_reason(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Required status check \"build\" is expected."
+            )
+            .as_deref(),
+            Some("required checks not met: Required status check \"build\" is expected.")
+        );
+        assert_eq!(
+            branch_protection_reason(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Base branch was modified. Review and try the merge again."
+            ),
+            None
+        );
+        assert_eq!(
+            branch_protection_reason(StatusCode::CONFLICT, "Required status check is expected."),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maybe_merge_one_deletes_branch() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/pulls/1/reviews"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/org/repo/pulls/1/merge"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(merge_json()))
+            .mount(&server)
+            .await;
+        // GitHub's automatic branch deletion got there first.
+        Mock::given(method("DELETE"))
+            .and(path("/repos/org/repo/git/refs/heads/dependabot/1"))
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "message": "Reference does not exist",
+                "documentation_url": "https://docs.github.com/rest",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let octo = mock_octocrab(&server);
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            delete_branch_after_merge = true
+            "#,
+        );
+        let github = GitHub {
+            octo: &octo,
+            retry: &cfg.retry,
+        };
+        let ctx = RunContext::new(&github, None, &cfg, false);
+        let mut report = RepoReport::new("org/repo");
+        let merged = maybe_merge_one(&ctx, &[dependabot_pr(1)], None, &mut report)
+            .await
+            .unwrap();
+        assert_eq!(merged.map(|pr| pr.number), Some(1));
+    }
+
+    #[test]
+    fn test_merge_commit() {
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            merge_commit_title = "Bump {dependency} to {new_version} (#{pr_number})"
+            "#,
+        );
+        assert_eq!(
+            MergeCommit::new(&cfg, &dependabot_pr(1)),
+            MergeCommit {
+                title: Some("Bump dep1 to 1.0.1 (#1)".to_string()),
+                message: None,
+            }
+        );
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        assert_eq!(
+            MergeCommit::new(&cfg, &dependabot_pr(1)),
+            MergeCommit::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_sends_commit_message() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/org/repo/pulls/1/merge"))
+            .and(body_partial_json(serde_json::json!({
+                "merge_method": "merge",
+                "commit_title": "Bump dep1",
+                "commit_message": "to 1.0.1",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(merge_json()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let octo = mock_octocrab(&server);
+        let retry = no_retry();
+        let github = GitHub::new(&octo, &retry);
+        let commit = MergeCommit {
+            title: Some("Bump dep1".to_string()),
+            message: Some("to 1.0.1".to_string()),
+        };
+        github
+            .merge(&dependabot_pr(1), Some(MergeMethod::Merge), &commit)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_merge_cooldown() {
+        let cfg = test_config("repos = []\nmerge_cooldown_seconds = 60");
+        let github = MockGitHub::default();
+        let ctx = RunContext::new(&github, None, &cfg, false);
+        let started = tokio::time::Instant::now();
+
+        for n in 1..=3 {
+            let mut report = RepoReport::new("org/repo");
+            maybe_merge_one(&ctx, &[dependabot_pr(n)], None, &mut report)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            github
+                .calls()
+                .iter()
+                .filter(|c| c.starts_with("merge"))
+                .count(),
+            3
+        );
+        assert_eq!(started.elapsed(), Duration::from_secs(120));
+    }
+
+    #[tokio::test]
+    async fn test_check_credentials() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rate_limit"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+                "message": "Bad credentials",
+                "documentation_url": "https://docs.github.com/rest",
+            })))
+            .mount(&server)
+            .await;
+
+        let err = check_credentials(&mock_octocrab(&server), &no_retry())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AutomergeError::Auth(_)));
+        assert_eq!(
+            err.to_string(),
+            "authentication failed: Bad credentials, check the token or the GitHub App credentials"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_rate_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rate_limit"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "resources": {
+                    "core": { "limit": 5000, "remaining": 100, "reset": 1714564800, "used": 4900 },
+                    "graphql": { "limit": 5000, "remaining": 5000, "reset": 1714564800, "used": 0 },
+                },
+            })))
+            .mount(&server)
+            .await;
+        let octo = mock_octocrab(&server);
+
+        assert!(check_rate_limit(&octo, &test_config("repos = []"))
+            .await
+            .is_ok());
+        let cfg = test_config("repos = []\nmin_rate_limit = 100");
+        assert!(check_rate_limit(&octo, &cfg).await.is_ok());
+        let cfg = test_config("repos = []\nmin_rate_limit = 101");
+        let err = check_rate_limit(&octo, &cfg).await.unwrap_err();
+        assert!(
+            err.to_string().contains("2024-05-01T12:00:00+00:00"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_run_outcome() {
+        let mut repo = RepoReport::new("org/repo");
+        let outcome = |repo: &RepoReport| {
+            RunReport {
+                repos: vec![repo.clone(), RepoReport::new("org/other")],
+                ..RunReport::default()
+            }
+            .outcome()
+        };
+        assert_eq!(outcome(&repo), RunOutcome::NoPrs);
+
+        repo.prs_found = 2;
+        repo.skip("https://github.com/org/repo/pull/1", "merge conflicts");
+        repo.skip("https://github.com/org/repo/pull/1", "not rebased");
+        assert_eq!(outcome(&repo), RunOutcome::AllSkipped);
+        assert_eq!(repo.skipped_prs(), 1);
+
+        repo.record("https://github.com/org/repo/pull/2", Action::Rebased, None);
+        assert_eq!(outcome(&repo), RunOutcome::Active);
+
+        let mut failed = RepoReport::new("org/repo");
+        failed.error = Some("Not Found".to_string());
+        assert_eq!(outcome(&failed), RunOutcome::Active);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let mut repo = RepoReport::new("org/repo");
+        repo.prs_found = 2;
+        repo.duration_secs = 1.5;
+        repo.record("https://github.com/org/repo/pull/1", Action::Merged, None);
+        repo.skip("https://github.com/org/repo/pull/2", "merge conflicts");
+        let report = RunReport {
+            repos: vec![repo],
+            ..RunReport::default()
+        };
+        let metrics = Arc::new(Metrics::default());
+        metrics.record(&report);
+        metrics.record(&report);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_metrics(listener, metrics));
+        let get = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        for line in [
+            "automerge_runs_total 2",
+            "automerge_prs_evaluated_total{repo=\"org/repo\"} 4",
+            "automerge_prs_total{repo=\"org/repo\",action=\"merged\"} 2",
+            "automerge_prs_total{repo=\"org/repo\",action=\"skipped\"} 2",
+            "automerge_repo_errors_total{repo=\"org/repo\"} 0",
+            "automerge_run_duration_seconds{repo=\"org/repo\"} 1.5",
+        ] {
+            assert!(response.lines().any(|l| l == line), "{}", response);
+        }
+        assert!(get("/").await.starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn test_api_usage_between() {
+        let limits = |core_remaining, core_reset, graphql_remaining| RateLimits {
+            resources: RateLimitResources {
+                core: RateLimit {
+                    limit: 5000,
+                    remaining: core_remaining,
+                    reset: core_reset,
+                },
+                graphql: Some(RateLimit {
+                    limit: 5000,
+                    remaining: graphql_remaining,
+                    reset: 1714564800,
+                }),
+            },
+        };
+
+        let usage = ApiUsage::between(
+            &limits(4000, 1714564800, 5000),
+            &limits(3950, 1714564800, 4998),
+        );
+        assert_eq!(
+            usage,
+            ApiUsage {
+                core: 50,
+                graphql: Some(2)
+            }
+        );
+
+        // The core window reset during the run.
+        let usage = ApiUsage::between(
+            &limits(100, 1714564800, 5000),
+            &limits(4990, 1714568400, 5000),
+        );
+        assert_eq!(usage.core, 10);
+    }
+
+    #[tokio::test]
+    async fn test_list_open_prs_follows_pagination() {
+        let server = MockServer::start().await;
+        let next_page = format!(
+            "<{}/repos/org/repo/pulls?state=open&per_page=100&page=2>; rel=\"next\"",
+            server.uri()
+        );
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls"))
+            .and(query_param_is_missing("page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Link", next_page.as_str())
+                    .set_body_json(serde_json::json!([pr_json(1), pr_json(2)])),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([pr_json(3)])))
+            .mount(&server)
+            .await;
+
+        let prs = list_open_prs(&mock_octocrab(&server), "org", "repo", &no_retry())
+            .await
+            .unwrap();
+        assert_eq!(
+            prs.iter().map(|pr| pr.number).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pr_commits_follows_pagination() {
+        let server = MockServer::start().await;
+        let next_page = format!(
+            "<{}/repos/org/repo/pulls/1/commits?per_page=100&page=2>; rel=\"next\"",
+            server.uri()
+        );
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/1/commits"))
+            .and(query_param_is_missing("page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Link", next_page.as_str())
+                    .set_body_json(serde_json::json!([
+                        { "author": { "login": "dependabot[bot]" } },
+                    ])),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/pulls/1/commits"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "author": { "login": "alice" } },
+            ])))
+            .mount(&server)
+            .await;
+
+        let commits = pr_commits(&mock_octocrab(&server), &dependabot_pr(1), &no_retry())
+            .await
+            .unwrap();
+        assert_eq!(
+            other_commit_authors(&commits, &["dependabot[bot]".to_string()]),
+            vec!["alice"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pr_comments_since_follows_pagination() {
+        let server = MockServer::start().await;
+        let comment = |body: &str| {
+            serde_json::json!({
+                "body": body,
+                "created_at": "2024-05-01T12:00:00Z",
+                "user": { "login": "dependabot[bot]" },
+            })
+        };
+        let next_page = format!(
+            "<{}/repos/org/repo/issues/1/comments?per_page=100&page=2>; rel=\"next\"",
+            server.uri()
+        );
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/issues/1/comments"))
+            .and(query_param_is_missing("page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Link", next_page.as_str())
+                    .set_body_json(vec![comment("Looks like this PR is already up-to-date")]),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/issues/1/comments"))
+            .and(query_param("page", "2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(vec![comment("Dependabot can't rebase this PR")]),
+            )
+            .mount(&server)
+            .await;
+
+        let comments = pr_comments_since(
+            &mock_octocrab(&server),
+            &dependabot_pr(1),
+            DateTime::UNIX_EPOCH,
+            &no_retry(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(comments.len(), 2);
+        assert!(rebase_failed(&comments, &["dependabot[bot]".to_string()]));
+    }
+
+    #[test]
+    fn test_parse_version_from_pr() {
+        assert_eq!(
+            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4"),
+            Some("1.2.4".to_string())
+        );
+        assert_eq!(
+            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4-alpha"),
+            Some("1.2.4-alpha".to_string())
+        );
+        assert_eq!(
+            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4-alpha.1"),
+            Some("1.2.4-alpha.1".to_string())
+        );
+        assert_eq!(
+            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4-alpha.1+build.1"),
+            Some("1.2.4-alpha.1+build.1".to_string())
+        );
+        assert_eq!(
+            parse_version_from_pr("Bump foo from 1.2.3a0+201.fbdbcb12 to 1.2.3a0+210.bafdcd99"),
+            Some("1.2.3a0+210.bafdcd99".to_string())
+        );
+        assert_eq!(
+            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4 in /frontend"),
+            Some("1.2.4".to_string())
+        );
+        assert_eq!(parse_version_from_pr("Bump foo to the latest"), None);
+    }
+
+    #[test]
+    fn test_version_kind() {
+        assert_eq!(version_kind("1.2.3"), Some(VersionKind::Semver));
+        assert_eq!(version_kind("v1.2.3-rc.1"), Some(VersionKind::Semver));
+        assert_eq!(version_kind("3.19"), Some(VersionKind::Short));
+        assert_eq!(version_kind("20"), Some(VersionKind::Short));
+        assert_eq!(version_kind("3.19-alpine"), Some(VersionKind::Short));
+        assert_eq!(
+            version_kind("0.0.0-20220127200216-cd36cc0744dd"),
+            Some(VersionKind::GoPseudo)
+        );
+        assert_eq!(
+            version_kind("v1.2.4-0.20230101120000-abcdef123456"),
+            Some(VersionKind::GoPseudo)
+        );
+        assert_eq!(version_kind("1a2b3c4"), Some(VersionKind::Digest));
+        assert_eq!(
+            version_kind("sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"),
+            Some(VersionKind::Digest)
+        );
+        assert_eq!(version_kind("latest"), None);
+        assert_eq!(version_kind(""), None);
+    }
+
+    #[test]
+    fn test_prerelease_of() {
+        assert_eq!(prerelease_of("1.2.4"), None);
+        assert_eq!(prerelease_of("1.2.4+build.1"), None);
+        assert_eq!(prerelease_of("1.2.4-alpha"), Some("alpha"));
+        assert_eq!(prerelease_of("1.2.4-alpha.1+build.1"), Some("alpha.1"));
+        assert_eq!(prerelease_of("1.2.3a0+210.bafdcd99"), Some("a0"));
+        assert_eq!(prerelease_of("3.19-alpine"), None);
+        assert_eq!(
+            prerelease_of("0.0.0-20220127200216-cd36cc0744dd"),
+            Some("20220127200216-cd36cc0744dd")
+        );
+        assert_eq!(prerelease_of(""), None);
+    }
+
+    #[test]
+    fn test_parse_version_range() {
+        assert_eq!(
+            parse_version_range("Bump foo from 1.2.3 to 1.2.4"),
+            (Some("1.2.3".to_string()), Some("1.2.4".to_string()))
+        );
+        assert_eq!(
+            parse_version_range("Bump foo from 1.2.3-alpha.1 to 1.2.4-alpha.1+build.1"),
+            (
+                Some("1.2.3-alpha.1".to_string()),
+                Some("1.2.4-alpha.1+build.1".to_string())
+            )
+        );
+        assert_eq!(
+            parse_version_range("Bump foo from 1.2.3a0+201.fbdbcb12 to 1.2.3a0+210.bafdcd99"),
+            (
+                Some("1.2.3a0+201.fbdbcb12".to_string()),
+                Some("1.2.3a0+210.bafdcd99".to_string())
+            )
+        );
+        assert_eq!(
+            parse_version_range("Bump alpine from 3.18 to 3.19"),
+            (Some("3.18".to_string()), Some("3.19".to_string()))
+        );
+        assert_eq!(
+            parse_version_range("Bump node from `1a2b3c4` to `5d6e7f8` in /docker"),
+            (Some("1a2b3c4".to_string()), Some("5d6e7f8".to_string()))
+        );
+        assert_eq!(
+            parse_version_range(
+                "Bump golang.org/x/net from 0.0.0-20220127200216-cd36cc0744dd to 0.7.0"
+            ),
+            (
+                Some("0.0.0-20220127200216-cd36cc0744dd".to_string()),
+                Some("0.7.0".to_string())
+            )
+        );
+        assert_eq!(
+            parse_version_range("Bump the npm-dependencies group with 5 updates"),
+            (None, None)
+        );
+        assert_eq!(
+            parse_version_range("Bump the cargo group across 1 directory with 1 update"),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_repo_settings() {
+        let cfg: Config = toml::from_str(
+            r#"
+            merge_method = "merge"
+            max_bump = "minor"
+            ignore_dependencies = ["aws-sdk-*"]
+            repos = [
+                "org/a",
+                { name = "org/b", required_checks = ["build"], merge_method = "squash" },
+                { name = "org/c", ignore_dependencies = [], min_age_hours = 24, paused = true },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let a = cfg.repo_settings(&cfg.repos[0]);
+        assert_eq!(
+            a,
+            RepoSettings {
+                name: "org/a".to_string(),
+                max_bump: Some(Bump::Minor),
+                merge_method: Some(MergeMethod::Merge),
+                action: RepoAction::Merge,
+                required_checks: vec![],
+                min_checks: 0,
+                ignore_dependencies: vec!["aws-sdk-*".to_string()],
+                ecosystems: vec![],
+                ignore_ecosystems: vec![],
+                require_labels: vec![],
+                exclude_labels: vec![],
+                base_branches: vec![],
+                min_age_hours: 0,
+                paused: false,
+            }
+        );
+
+        let b = cfg.repo_settings(&cfg.repos[1]);
+        assert_eq!(b.name, "org/b");
+        assert_eq!(b.merge_method, Some(MergeMethod::Squash));
+        assert_eq!(b.required_checks, vec!["build".to_string()]);
+        assert_eq!(b.ignore_dependencies, vec!["aws-sdk-*".to_string()]);
+
+        let c = cfg.repo_settings(&cfg.repos[2]);
+        assert_eq!(c.merge_method, Some(MergeMethod::Merge));
+        assert!(c.ignore_dependencies.is_empty());
+        assert_eq!(c.min_age_hours, 24);
+        assert!(c.paused);
+    }
+
+    #[test]
+    fn test_checks_pass() {
+        let run = |name: &str, status: &str, conclusion: Option<&str>| CheckRun {
+            name: name.to_string(),
+            status: status.to_string(),
+            conclusion: conclusion.map(|c| c.to_string()),
+        };
+        let required = vec!["build".to_string()];
+        let blocking = default_blocking_conclusions();
+
+        assert!(checks_pass(&[], &[], 0, &blocking));
+        assert!(checks_pass(
+            &[
+                run("build", "completed", Some("success")),
+                run("lint", "completed", Some("skipped")),
+                run("docs", "completed", Some("neutral")),
+            ],
+            &required,
+            0,
+            &blocking
+        ));
+        assert!(!checks_pass(
+            &[run("build", "completed", Some("failure"))],
+            &[],
+            0,
+            &blocking
+        ));
+        for conclusion in ["cancelled", "timed_out", "action_required", "stale"] {
+            let runs = [run("build", "completed", Some(conclusion))];
+            assert!(!checks_pass(&runs, &[], 0, &blocking), "{}", conclusion);
+        }
+        let cancelled = [run("build", "completed", Some("cancelled"))];
+        assert!(checks_pass(&cancelled, &[], 0, &["failure".to_string()]));
+        assert!(!checks_pass(
+            &[run("build", "in_progress", None)],
+            &[],
+            0,
+            &blocking
+        ));
+        assert!(!checks_pass(
+            &[run("build", "queued", None)],
+            &[],
+            0,
+            &blocking
+        ));
+        assert!(!checks_pass(
+            &[run("lint", "completed", Some("success"))],
+            &required,
+            0,
+            &blocking
+        ));
+
+        assert!(!checks_pass(&[], &[], 1, &blocking));
+        assert!(!checks_pass(
+            &[run("build", "completed", Some("skipped"))],
+            &[],
+            1,
+            &blocking
+        ));
+        assert!(checks_pass(
+            &[
+                run("build", "completed", Some("success")),
+                run("lint", "completed", Some("skipped")),
+            ],
+            &[],
+            1,
+            &blocking
+        ));
+
+        let lint_only = [run("lint", "completed", Some("success"))];
+        assert!(!checks_pass(&lint_only, &required, 0, &blocking));
+        assert_eq!(missing_checks(&lint_only, &required), vec!["build"]);
+        assert!(missing_checks(&[run("build", "queued", None)], &required).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_commit_checks() {
+        let server = MockServer::start().await;
+        let run = |name: &str| serde_json::json!({ "name": name, "status": "completed", "conclusion": "success" });
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/commits/head1/check-runs"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "total_count": 3,
+                "check_runs": [run("build (linux)"), run("build (macos)")],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/commits/head1/check-runs"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "total_count": 3,
+                "check_runs": [run("build (windows)")],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/repo/commits/head1/status"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "state": "pending",
+                "total_count": 1,
+                "statuses": [{ "context": "ci/jenkins", "state": "pending" }],
+            })))
+            .mount(&server)
+            .await;
+
+        let blocking = default_blocking_conclusions();
+        let runs = commit_checks(&mock_octocrab(&server), "org", "repo", "head1", &no_retry())
+            .await
+            .unwrap();
+        assert_eq!(
+            runs.iter().map(|run| run.name.as_str()).collect::<Vec<_>>(),
+            vec![
+                "build (linux)",
+                "build (macos)",
+                "build (windows)",
+                "ci/jenkins"
+            ]
+        );
+        assert!(!checks_pass(&runs, &[], 0, &blocking));
+        let jenkins = CommitStatus {
+            context: "ci/jenkins".to_string(),
+            state: "success".to_string(),
+        };
+        assert!(checks_pass(
+            &[jenkins.as_check_run()],
+            &["ci/jenkins".to_string()],
+            1,
+            &blocking
+        ));
+
+        let truncated = CombinedStatus {
+            state: "failure".to_string(),
+            total_count: 2,
+            statuses: vec![jenkins],
+        };
+        assert!(!checks_pass(&truncated.check_runs(), &[], 0, &blocking));
+    }
+
+    #[test]
+    fn test_parse_dependency_from_pr() {
+        assert_eq!(
+            parse_dependency_from_pr("Bump foo from 1.2.3 to 1.2.4"),
+            Some("foo".to_string())
+        );
+        assert_eq!(
+            parse_dependency_from_pr("Bump foo from 1.2.3 to 1.2.4 in /frontend"),
+            Some("foo".to_string())
+        );
+        assert_eq!(
+            parse_dependency_from_pr("Bump @types/node from 20.1.0 to 20.2.0"),
+            Some("@types/node".to_string())
+        );
+        assert_eq!(
+            parse_dependency_from_pr("chore(deps): bump serde_json from 1.0.116 to 1.0.117"),
+            Some("serde_json".to_string())
+        );
+        assert_eq!(
+            parse_dependency_from_pr("Update tokio requirement from 1.36.0 to 1.37.0"),
+            Some("tokio".to_string())
+        );
+        assert_eq!(
+            parse_dependency_from_pr(
+                "Bump torch from 1.2.3a0+201.fbdbcb12 to 1.2.3a0+210.bafdcd99"
+            ),
+            Some("torch".to_string())
+        );
+        assert_eq!(
+            parse_dependency_from_pr("Bump the aws group with 3 updates"),
+            None
+        );
+        assert_eq!(
+            parse_dependency_from_pr(
+                "Bump the npm_and_yarn group across 1 directory with 2 updates"
+            ),
+            None
+        );
+        assert_eq!(parse_dependency_from_pr("Update README"), None);
+    }
+
+    #[test]
+    fn test_group_updates() {
+        assert!(is_group_pr(
+            "Bump the npm-dependencies group with 5 updates"
+        ));
+        assert!(is_group_pr(
+            "chore(deps): bump the cargo group across 1 directory with 2 updates"
+        ));
+        assert!(!is_group_pr("Bump serde from 1.0.197 to 1.0.198"));
+
+        let body = "Bumps the cargo group with 2 updates: [serde](https://github.com/serde-rs/serde) and [tokio](https://github.com/tokio-rs/tokio).\n\
+                    \n\
+                    Updates `serde` from 1.0.197 to 1.0.198\n\
+                    <details>...</details>\n\
+                    \n\
+                    Updates `tokio` from 1.36.0 to 1.37.0-rc.1\n";
+        assert_eq!(
+            parse_group_updates(body),
+            vec![
+                DependencyUpdate {
+                    name: "serde".to_string(),
+                    old_version: "1.0.197".to_string(),
+                    new_version: "1.0.198".to_string(),
+                },
+                DependencyUpdate {
+                    name: "tokio".to_string(),
+                    old_version: "1.36.0".to_string(),
+                    new_version: "1.37.0-rc.1".to_string(),
+                },
+            ]
+        );
+        assert!(parse_group_updates("Bumps the cargo group.").is_empty());
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("serde", "serde"));
+        assert!(!matches_pattern("serde", "serde_json"));
+        assert!(matches_pattern("aws-sdk-*", "aws-sdk-s3"));
+        assert!(!matches_pattern("aws-sdk-*", "aws-config"));
+        assert!(matches_pattern("*eslint*", "@typescript-eslint/parser"));
+        assert!(matches_pattern("@types/*", "@types/node"));
+        assert!(!matches_pattern("a.c", "abc"));
+    }
+
+    #[test]
+    fn test_label_skip_reason() {
+        let labels = vec!["dependencies".to_string(), "automerge".to_string()];
+        let none: &[String] = &[];
+        assert_eq!(label_skip_reason(&labels, none, none), None);
+        assert_eq!(
+            label_skip_reason(&labels, &["automerge".to_string()], none),
+            None
+        );
+        assert_eq!(
+            label_skip_reason(&[], &["automerge".to_string()], none),
+            Some("missing label \"automerge\"".to_string())
+        );
+        assert_eq!(
+            label_skip_reason(&labels, none, &["automerge".to_string()]),
+            Some("has label \"automerge\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bump_level() {
+        assert_eq!(bump_level("1.2.3", "1.2.4"), Some(Bump::Patch));
+        assert_eq!(bump_level("1.2.3", "1.3.0"), Some(Bump::Minor));
+        assert_eq!(bump_level("1.2.3", "2.0.0"), Some(Bump::Major));
+        assert_eq!(bump_level("1.2.3", "1.2.4-alpha.1"), Some(Bump::Patch));
+        assert_eq!(bump_level("1.2.4-alpha.1", "1.2.4"), Some(Bump::Patch));
+        assert_eq!(
+            bump_level("1.2.3a0+201.fbdbcb12", "1.2.3a0+210.bafdcd99"),
+            Some(Bump::Patch)
+        );
+        assert_eq!(bump_level("", "1.2.4"), None);
+        assert_eq!(bump_level("3.18", "3.19"), Some(Bump::Minor));
+        assert_eq!(bump_level("18", "20"), Some(Bump::Major));
+        assert_eq!(bump_level("v1.2.3", "v1.2.4"), Some(Bump::Patch));
+        assert_eq!(bump_level("1a2b3c4", "5d6e7f8"), None);
+    }
+
+    #[test]
+    fn test_title_skip_reason() {
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            title_allow_regex = "^Bump (serde|tokio)"
+            title_deny_regex = "to 2\\."
+            "#,
+        );
+        assert_eq!(
+            title_skip_reason("Bump serde from 1.0.1 to 1.0.2", &cfg),
+            None
+        );
+        assert_eq!(
+            title_skip_reason("Bump rand from 0.8.0 to 0.8.1", &cfg),
+            Some("title doesn't match title_allow_regex")
+        );
+        assert_eq!(
+            title_skip_reason("Bump tokio from 1.0.0 to 2.0.0", &cfg),
+            Some("title matches title_deny_regex")
+        );
+        assert_eq!(
+            title_skip_reason("anything", &test_config(r#"repos = ["org/repo"]"#)),
+            None
+        );
+
+        let err = toml::from_str::<Config>(
+            r#"
+            repos = ["org/repo"]
+            title_deny_regex = "(unclosed"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("title_deny_regex"), "{}", err);
+    }
+
+    #[test]
+    fn test_title_versions() {
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            version_regex = "auf (\\S+) aktualisiert"
+            "#,
+        );
+        assert_eq!(
+            title_versions("foo auf 2.1.0-beta aktualisiert", &cfg),
+            (None, Some("2.1.0-beta".to_string()))
+        );
+        assert_eq!(
+            title_versions("Bump foo from 1.2.3 to 1.2.4", &cfg),
+            (Some("1.2.3".to_string()), Some("1.2.4".to_string()))
+        );
+        assert_eq!(
+            title_versions("foo auf 2.1.0 aktualisiert", &test_config("repos = []")),
+            (None, None)
+        );
+
+        let cfg = test_config(
+            r#"
+            repos = ["org/repo"]
+            version_regex = "to \\S+"
+            "#,
+        );
+        let err = validate_config(&cfg, Some("token".to_string())).unwrap_err();
+        assert!(err.contains("no capture group"), "{}", err);
+        let err = toml::from_str::<Config>("repos = []\nversion_regex = \"(\"").unwrap_err();
+        assert!(err.to_string().contains("version_regex"), "{}", err);
+    }
+
+    #[test]
+    fn test_ceiling_skip_reason() {
+        let update = |name: &str, new_version: &str| DependencyUpdate {
+            name: name.to_string(),
+            old_version: "1.0.0".to_string(),
+            new_version: new_version.to_string(),
+        };
+        let ceilings = HashMap::from([("tokio".to_string(), "1.999".to_string())]);
+        assert_eq!(
+            ceiling_skip_reason(&[update("tokio", "1.40.0")], &ceilings),
+            None
+        );
+        assert_eq!(
+            ceiling_skip_reason(&[update("tokio", "1.999.0")], &ceilings),
+            None
+        );
+        assert_eq!(
+            ceiling_skip_reason(&[update("tokio", "2.0.0")], &ceilings).as_deref(),
+            Some("tokio 2.0.0 is above the 1.999 ceiling")
+        );
+        assert_eq!(
+            ceiling_skip_reason(
+                &[update("serde", "2.0.0"), update("tokio", "abcdef1")],
+                &ceilings
+            )
+            .as_deref(),
+            Some("tokio abcdef1 can't be compared to the 1.999 ceiling")
+        );
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        use std::cmp::Ordering;
+        assert_eq!(compare_versions("1.10.0", "1.9.3"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("v1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(
+            compare_versions("1.2.3", "2.0.0-rc.1"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(compare_versions("1a2b3c4", "5d6e7f8"), None);
+        assert_eq!(compare_versions("", "1.0.0"), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_prs_skips_superseded_prs() {
+        let bump = |number, version: &str| DependabotPr {
+            dependency_name: "serde".to_string(),
+            new_version: version.to_string(),
+            head_ref: format!("dependabot/cargo/serde-{}", version),
+            ..dependabot_pr(number)
+        };
+        let other_dir = DependabotPr {
+            head_ref: "dependabot/cargo/crates/cli/serde-1.0.2".to_string(),
+            ..bump(4, "1.0.2")
+        };
+        let github = MockGitHub::with_prs(vec![
+            bump(1, "1.0.1"),
+            bump(2, "1.0.10"),
+            bump(3, "1.0.2"),
+            other_dir,
+        ]);
+        let cfg = test_config(r#"repos = ["org/repo"]"#);
+        let report = check_mock_prs(&github, &cfg, false).await;
+        assert_eq!(
+            report.events[0].reason.as_deref(),
+            Some("superseded by https://github.com/org/repo/pull/2")
+        );
+        assert_eq!(
+            actions(&report)[..2],
+            [(1, Action::Skipped), (3, Action::Skipped)]
+        );
+        assert_eq!(github.calls(), vec!["approve 2", "merge 2"]);
+    }
+
+    #[tokio::test]
+    async fn test_expand_repos() {
+        let cfg = test_config(
+            r#"
+            repos = [
+                { name = "org/api", merge_method = "rebase" },
+                { name = "org/*", merge_method = "squash" },
+                "other/*",
+            ]
+            exclude_repos = ["org/legacy-*"]
+            "#,
+        );
+        let repo = |full_name: &str, archived: bool| RepoInfo {
+            full_name: full_name.to_string(),
+            archived,
+            disabled: false,
+            permissions: None,
+        };
+        let github = MockGitHub {
+            owner_repos: vec![
+                repo("org/api", false),
+                repo("org/web", false),
+                repo("org/old", true),
+                repo("org/legacy-app", false),
+                repo("other/tool", false),
+            ],
+            ..MockGitHub::default()
+        };
+
+        let (repos, failed) = expand_repos(&github, &cfg).await;
+        assert!(failed.is_empty());
+        let names: Vec<&str> = repos.iter().map(|repo| repo.name()).collect();
+        assert_eq!(names, vec!["org/api", "org/web", "other/tool"]);
+        assert_eq!(
+            cfg.repo_settings(&repos[0]).merge_method,
+            Some(MergeMethod::Rebase)
+        );
+        assert_eq!(
+            cfg.repo_settings(&repos[1]).merge_method,
+            Some(MergeMethod::Squash)
+        );
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let err = validate_config(
+            &test_config(r#"repos = ["org/repo", "no-slash", "a/b/c", { name = "/repo" }]"#),
+            None,
+        )
+        .unwrap_err();
+        assert!(err.contains("\"no-slash\""), "{}", err);
+        assert!(err.contains("\"a/b/c\""), "{}", err);
+        assert!(err.contains("\"/repo\""), "{}", err);
+        assert!(err.contains("token not found"), "{}", err);
+
+        let err = validate_config(&test_config(r#"repos = ["*/repo"]"#), Some("token".into()))
+            .unwrap_err();
+        assert!(err.contains("\"*/repo\""), "{}", err);
+        assert!(!err.contains("\"org/repo\""), "{}", err);
+
+        let err = validate_config(&test_config("repos = []"), Some("token".into())).unwrap_err();
+        assert!(err.contains("`repos` is empty"), "{}", err);
+
+        let err = validate_config(
+            &test_config(
+                r#"
+                repos = ["org/repo"]
+                notify_webhook = "hooks.slack.com/services/x"
+                "#,
+            ),
+            Some("token".into()),
+        )
+        .unwrap_err();
+        assert!(err.contains("notify_webhook"), "{}", err);
+
+        let err = validate_config(
+            &test_config(
+                r#"
+                repos = ["org/repo"]
+                rebase_command = "please rebase"
+                "#,
+            ),
+            Some("token".into()),
+        )
+        .unwrap_err();
+        assert!(err.contains("rebase_command"), "{}", err);
+
+        let err = validate_config(
+            &test_config(
+                r#"
+                repos = ["org/repo"]
+                version_ceilings = { tokio = "latest" }
+                "#,
+            ),
+            Some("token".into()),
+        )
+        .unwrap_err();
+        assert!(err.contains("version_ceilings.tokio"), "{}", err);
+
+        let err = validate_config(
+            &test_config(
+                r#"
+                repos = ["org/repo"]
+                api_url = "github.example.com"
+                "#,
+            ),
+            Some("token".into()),
+        )
+        .unwrap_err();
+        assert!(err.contains("api_url"), "{}", err);
+        assert!(validate_config(
+            &test_config(
+                r#"
+                repos = ["org/repo"]
+                api_url = "https://github.example.com/api/v3"
+                "#,
+            ),
+            Some("token".into()),
+        )
+        .is_ok());
+
+        assert_eq!(
+            validate_config(
+                &test_config(r#"repos = ["org/repo"]"#),
+                Some("token".into())
+            ),
+            Ok(Credentials::Token("token".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_fields() {
+        assert!(toml::from_str::<Config>(
+            r#"
+            repos = ["org/repo"]
+            merge_mehtod = "squash"
+            "#
+        )
+        .is_err());
+        assert!(toml::from_str::<Config>(
+            r#"repos = [{ name = "org/repo", requried_checks = [] }]"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_repo_name() {
+        assert_eq!(parse_repo_name("org/repo"), Some(("org", "repo")));
+        assert_eq!(parse_repo_name("org"), None);
+        assert_eq!(parse_repo_name("org/"), None);
+        assert_eq!(parse_repo_name("org/repo/extra"), None);
+        assert_eq!(parse_repo_name("org/my repo"), None);
+    }
+
+    #[test]
+    fn test_resolve_credentials() {
+        let app = r#"
+            repos = []
+            [auth]
+            app_id = 1
+            installation_id = 2
+            private_key_path = "key.pem"
+        "#;
+
+        assert_eq!(
+            resolve_credentials(&test_config(app), Some("env".into())),
+            Ok(Credentials::App {
+                app_id: 1,
+                installation_id: 2,
+                private_key_path: "key.pem".to_string(),
+            })
+        );
+        assert_eq!(
+            resolve_credentials(&test_config("repos = []"), Some("env".into())),
+            Ok(Credentials::Token("env".to_string()))
+        );
+        assert!(resolve_credentials(&test_config("repos = []"), None).is_err());
+        assert!(resolve_credentials(
+            &test_config(&format!("github_token = \"cfg\"\n{}", app)),
+            None
+        )
+        .is_err());
+
+        let token_file =
+            std::env::temp_dir().join(format!("automerge-token-{}", std::process::id()));
+        fs::write(&token_file, "file-token\n").unwrap();
+        let with_file = format!("repos = []\ngithub_token_file = {:?}", token_file);
+        assert_eq!(
+            resolve_credentials(&test_config(&with_file), Some("env".into())),
+            Ok(Credentials::Token("file-token".to_string()))
+        );
+        fs::write(&token_file, " \n").unwrap();
+        let err = resolve_credentials(&test_config(&with_file), None).unwrap_err();
+        assert!(err.contains("is empty"), "{}", err);
+        fs::remove_file(&token_file).unwrap();
+        let err = resolve_credentials(&test_config(&with_file), None).unwrap_err();
+        assert!(err.contains("Failed to read github_token_file"), "{}", err);
+    }
+
+    #[test]
+    fn test_resolve_token() {
+        assert_eq!(
+            resolve_token(Some("cfg".into()), Some("env".into())),
+            Some("cfg".to_string())
+        );
+        assert_eq!(
+            resolve_token(Some("".into()), Some("env".into())),
+            Some("env".to_string())
+        );
+        assert_eq!(
+            resolve_token(None, Some("env".into())),
+            Some("env".to_string())
+        );
+        assert_eq!(resolve_token(None, Some("".into())), None);
+        assert_eq!(resolve_token(None, None), None);
+    }
+}
@@ -1,241 +1,958 @@
-//! Automatically rebases and merges dependabot PRs.
-//! Requires a personal GitHub token.
-
-use log::{self, error, info};
-use octocrab::params::repos::Reference;
-use octocrab::{params, Octocrab};
-use regex::Regex;
-use serde::Deserialize;
-use serde::Serialize;
-
+//! Command line interface of the `automerge` library.
+
+use automerge::{
+    build_octocrab, check_credentials, check_rate_limit, doctor, list_prs, run_once, serve_metrics,
+    validate_config, Config, HealthCheck, Metrics, Notifier, PrListing, RunOutcome, RunReport,
+};
+use log::kv::{self, VisitSource, VisitValue};
+use log::{error, info, warn, Log};
+use octocrab::Octocrab;
+use simple_logger::SimpleLogger;
+
+use std::env;
 use std::error::Error;
+use std::ffi::OsString;
 use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Command line arguments.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Args {
+    /// Print a table of the PRs and whether they'd be merged, then exit.
+    list: bool,
+    /// Check connectivity and the token's permissions in every repo, then exit.
+    doctor: bool,
+    /// Print the PRs a run would still merge or rebase, then exit with an
+    /// error if there are any, e.g. to gate deploys in CI.
+    check: bool,
+    /// Log the actions that would be taken instead of performing them.
+    dry_run: bool,
+    /// Check the repos once and exit, which is also what happens without
+    /// `--watch`. Spelled out for cron jobs.
+    once: bool,
+    /// Keep running and check the repos every `interval` seconds.
+    watch: bool,
+    /// Seconds between the runs of `--watch`, `DEFAULT_INTERVAL_SECS` if
+    /// not given.
+    interval: Option<u64>,
+    /// Print a JSON `RunReport` to stdout after every run, one per line, so
+    /// that it can be piped into e.g. `jq`. Logs always go to stderr.
+    json_output: bool,
+    /// Leave every repo alone, overriding `paused` in the config. Runs don't
+    /// talk to GitHub and report every repo as paused.
+    pause: bool,
+    /// Exit successfully even when some repos failed. Single runs exit with
+    /// an error otherwise, so that CI notices.
+    best_effort: bool,
+    /// Overrides `RUST_LOG`. Defaults to `info`.
+    log_level: Option<log::LevelFilter>,
+    /// Log JSON lines to stderr instead of text.
+    json_logs: bool,
+    /// Config files or directories of them to use instead of looking the
+    /// config up with `find_config`, merged by `load_config`.
+    config: Vec<PathBuf>,
+}
 
-#[derive(Debug, Clone, Deserialize)]
-struct Config {
-    github_token: String,
-    repos: Vec<String>,
+/// Polling interval used by `--watch` unless `--interval` is given.
+const DEFAULT_INTERVAL_SECS: u64 = 300;
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "list" => parsed.list = true,
+                "doctor" => parsed.doctor = true,
+                "check" => parsed.check = true,
+                "--dry-run" => parsed.dry_run = true,
+                "--best-effort" => parsed.best_effort = true,
+                "--pause" => parsed.pause = true,
+                "--once" => parsed.once = true,
+                "--watch" => parsed.watch = true,
+                "--interval" => {
+                    let secs = args
+                        .next()
+                        .ok_or("--interval requires a number of seconds")?;
+                    let secs = secs
+                        .parse()
+                        .map_err(|_| format!("Invalid --interval: {}", secs))?;
+                    parsed.interval = Some(secs);
+                }
+                "--config" => {
+                    let path = args.next().ok_or("--config requires a path")?;
+                    parsed.config.push(PathBuf::from(path));
+                }
+                "--log-level" => {
+                    let level = args.next().ok_or("--log-level requires a level")?;
+                    let level = level
+                        .parse()
+                        .map_err(|_| format!("Invalid --log-level: {}", level))?;
+                    parsed.log_level = Some(level);
+                }
+                "--log-format" => match args.next().as_deref() {
+                    Some("json") => parsed.json_logs = true,
+                    Some("text") => parsed.json_logs = false,
+                    _ => return Err("--log-format must be `text` or `json`".to_string()),
+                },
+                "--output" => match args.next().as_deref() {
+                    Some("json") => parsed.json_output = true,
+                    Some("text") => parsed.json_output = false,
+                    _ => return Err("--output must be `text` or `json`".to_string()),
+                },
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+        if parsed.once && parsed.watch {
+            return Err("--once and --watch can't be used together".to_string());
+        }
+        if parsed.interval.is_some() && !parsed.watch {
+            return Err("--interval only applies to --watch".to_string());
+        }
+        Ok(parsed)
+    }
 }
 
-#[derive(Debug, Clone)]
-struct Repo {
-    org: String,
-    repo: String,
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-struct DependabotPr {
-    url: String,
-    number: u64,
-    repo: Repo,
+async fn run() -> Result<ExitCode, Box<dyn Error>> {
+    let args = Args::parse(env::args().skip(1))?;
 
-    all_checks_pass: bool,
-    // PR rebased off a base branch.
-    rebased: bool,
-    rebase_in_progress: bool,
+    if args.json_logs {
+        let level = args
+            .log_level
+            .or_else(|| env::var("RUST_LOG").ok()?.parse().ok())
+            .unwrap_or(log::LevelFilter::Info);
+        log::set_boxed_logger(Box::new(JsonLogger { level }))?;
+        log::set_max_level(level);
+    } else {
+        let mut logger = SimpleLogger::new().with_level(log::LevelFilter::Info).env();
+        if let Some(level) = args.log_level {
+            logger = logger.with_level(level);
+        }
+        logger.init()?;
+    }
 
-    new_version: String,
-}
+    let cfg_paths = if args.config.is_empty() {
+        vec![find_config(
+            env::var_os("XDG_CONFIG_HOME"),
+            env::var_os("HOME"),
+        )]
+    } else {
+        args.config.clone()
+    };
+    let mut cfg = load_config(&cfg_paths)?;
+    cfg.paused |= args.pause;
+
+    let credentials = validate_config(&cfg, env::var("GITHUB_TOKEN").ok())?;
+    let timeout = Duration::from_secs(cfg.request_timeout_secs);
+    let mut octo = build_octocrab(&credentials, cfg.api_url.as_deref(), timeout)?;
+    let notifier = cfg
+        .notify_webhook
+        .as_deref()
+        .map(|url| Notifier::new(url, timeout))
+        .transpose()?;
+
+    if args.doctor {
+        let checks = doctor(&octo, &cfg).await;
+        print!("{}", format_health_checks(&checks));
+        let failed = checks.iter().filter(|check| !check.ok).count();
+        if failed > 0 {
+            return Err(format!("{} of {} checks failed", failed, checks.len()).into());
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
-struct IgnoreResp {}
+    // Doctor reports this itself. Paused runs don't talk to GitHub.
+    if !cfg.paused || args.list || args.check {
+        check_credentials(&octo, &cfg.retry).await?;
+    }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    simple_logger::init_with_level(log::Level::Info)?;
+    if args.list {
+        let (listings, failed) = list_prs(&octo, &cfg).await;
+        print!("{}", format_listings(&listings));
+        if !failed.is_empty() {
+            return Err(format!("Failed to list {}", failed.join(", ")).into());
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    let cfg_str = fs::read_to_string("config.toml")?;
-    let cfg: Config = toml::from_str(&cfg_str)?;
+    if args.check {
+        let (listings, failed) = list_prs(&octo, &cfg).await;
+        let pending: Vec<PrListing> = listings
+            .into_iter()
+            .filter(|listing| listing.pending)
+            .collect();
+        print!("{}", format_pending(&pending));
+        // Repos that couldn't be checked might have PRs pending.
+        if !failed.is_empty() {
+            return Err(format!("Failed to check {}", failed.join(", ")).into());
+        }
+        if !pending.is_empty() {
+            return Err(format!("{} PRs pending", pending.len()).into());
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    let octo = Octocrab::builder()
-        .personal_token(cfg.github_token)
-        .build()?;
+    if !args.watch {
+        let limits = if cfg.paused {
+            None
+        } else {
+            check_rate_limit(&octo, &cfg).await?
+        };
+        let report = run_once(&octo, notifier.as_ref(), &cfg, args.dry_run, limits).await;
+        write_report(&report, &cfg, &args);
+        let failed = report.failed_repos();
+        if !args.best_effort && failed > 0 {
+            return Err(format!("{} repos failed", failed).into());
+        }
+        return Ok(ExitCode::from(exit_code(&report, &cfg)));
+    }
 
-    for repo in cfg.repos.iter() {
-        if let Err(e) = check_prs(&octo, repo).await {
-            error!("[{}] Error: {:?}", repo, e);
+    let interval = args.interval.unwrap_or(DEFAULT_INTERVAL_SECS);
+    let metrics = match &cfg.metrics_addr {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr)
+                .await
+                .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+            info!("Serving metrics at http://{}/metrics", addr);
+            let metrics = Arc::new(Metrics::default());
+            tokio::spawn(serve_metrics(listener, metrics.clone()));
+            Some(metrics)
+        }
+        None => None,
+    };
+
+    let mut shutdown = tokio::spawn(shutdown_signal());
+    loop {
+        let limits = if cfg.paused {
+            Ok(None)
+        } else {
+            match refresh_credentials(&mut octo, &cfg).await {
+                Ok(()) => check_rate_limit(&octo, &cfg).await,
+                Err(e) => Err(e),
+            }
+        };
+        match limits {
+            Ok(limits) => {
+                let report = run_once(&octo, notifier.as_ref(), &cfg, args.dry_run, limits).await;
+                write_report(&report, &cfg, &args);
+                if let Some(metrics) = &metrics {
+                    metrics.record(&report);
+                }
+            }
+            Err(e) => error!("Skipping this run: {}", e),
+        }
+        if shutdown.is_finished() {
+            break;
+        }
+
+        info!("Run finished, next check in {}s", interval);
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(interval)) => {}
+            _ = &mut shutdown => break,
         }
     }
+    info!("Shutting down");
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-async fn check_prs(octo: &Octocrab, repo: &str) -> Result<(), Box<dyn Error>> {
-    let prs = dependabot_prs_passing_checks(octo, repo).await?;
-    if prs.is_empty() {
-        info!("[{}] No dependabot PRs to merge", repo);
-        return Ok(());
+/// `no_prs_exit_code` or `all_skipped_exit_code` when the run did nothing.
+fn exit_code(report: &RunReport, cfg: &Config) -> u8 {
+    match report.outcome() {
+        RunOutcome::NoPrs => cfg.no_prs_exit_code,
+        RunOutcome::AllSkipped => cfg.all_skipped_exit_code,
+        RunOutcome::Paused | RunOutcome::Active => 0,
     }
+}
 
-    if prs.iter().any(|pr| pr.rebase_in_progress) {
-        info!(
-            "[{}] One of the PRs is being rebased already. Skipping further actions.",
-            repo
-        );
-        return Ok(());
+/// Rebuilds the client when GitHub rejects its credentials, so that `--watch`
+/// survives expired tokens: `github_token_file` and the GitHub App private
+/// key are read again. This only happens between runs, repos left in a run
+/// when the token expires fail and are checked again in the next one.
+async fn refresh_credentials(octo: &mut Octocrab, cfg: &Config) -> Result<(), Box<dyn Error>> {
+    match check_credentials(octo, &cfg.retry).await {
+        Err(e) if e.is_unauthorized() => {
+            warn!("{}, reloading the credentials", e);
+            let credentials = validate_config(cfg, env::var("GITHUB_TOKEN").ok())?;
+            let timeout = Duration::from_secs(cfg.request_timeout_secs);
+            *octo = build_octocrab(&credentials, cfg.api_url.as_deref(), timeout)?;
+            Ok(check_credentials(octo, &cfg.retry).await?)
+        }
+        result => Ok(result?),
     }
+}
 
-    // Don't merge pre-release versions automatically.
-    let prs = prs
-        .into_iter()
-        .filter(|pr| !pr.new_version.contains('+'))
-        .collect::<Vec<_>>();
+/// Logs a JSON object per line, with the key-values of the records, e.g.
+/// the `repo`, `pr`, `action` and `reason` of decisions, as fields.
+struct JsonLogger {
+    level: log::LevelFilter,
+}
 
-    let maybe_rebase = if let Some(merged) = maybe_merge_one(octo, &prs).await? {
-        prs.iter().find(|pr| pr.url != merged.url && !pr.rebased)
-    } else {
-        prs.iter().find(|pr| !pr.rebased)
-    };
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
 
-    if let Some(to_rebase) = maybe_rebase {
-        info!("Rebasing {:?}", to_rebase.url);
-        octo.issues(&to_rebase.repo.org, &to_rebase.repo.repo)
-            .create_comment(to_rebase.number, "@dependabot rebase")
-            .await?;
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", json_log_line(record, chrono::Utc::now()));
+        }
     }
 
-    Ok(())
+    fn flush(&self) {}
 }
 
-async fn maybe_merge_one(
-    octo: &Octocrab,
-    prs: &[DependabotPr],
-) -> Result<Option<DependabotPr>, Box<dyn Error>> {
-    if let Some(pr) = prs.iter().find(|pr| pr.all_checks_pass && pr.rebased) {
-        info!("Merging {:?}", pr.url);
+fn json_log_line(record: &log::Record, now: chrono::DateTime<chrono::Utc>) -> serde_json::Value {
+    let mut line = serde_json::json!({
+        "timestamp": now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    if let Some(fields) = line.as_object_mut() {
+        let _ = record.key_values().visit(&mut JsonFields(fields));
+    }
+    line
+}
 
-        // Approve
-        let url = format!(
-            "/repos/{}/{}/pulls/{}/reviews",
-            pr.repo.org, pr.repo.repo, pr.number
-        );
-        let review_body = serde_json::json!({
-            "event": "APPROVE"
-        });
-        let _resp: IgnoreResp = octo.post(url, Some(&review_body)).await?;
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
 
-        // Merge
-        let url = format!(
-            "/repos/{}/{}/pulls/{}/merge",
-            pr.repo.org, pr.repo.repo, pr.number
-        );
-        let res: octocrab::Result<IgnoreResp> = octo.put(url, None::<&()>).await;
-        if let Err(e) = res {
-            info!("Failed to merge {:?}: {:?}", pr.url, e);
-            return Ok(None);
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let mut json = JsonValue(serde_json::Value::Null);
+        value.visit(&mut json)?;
+        self.0.insert(key.as_str().to_string(), json.0);
+        Ok(())
+    }
+}
+
+struct JsonValue(serde_json::Value);
+
+impl<'v> VisitValue<'v> for JsonValue {
+    fn visit_any(&mut self, value: kv::Value) -> Result<(), kv::Error> {
+        self.0 = value.to_string().into();
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+}
+
+/// Prints the report and writes it to `report_file`, as configured.
+fn write_report(report: &RunReport, cfg: &Config, args: &Args) {
+    // Set when running as a GitHub Actions step.
+    if let Some(path) = env::var_os("GITHUB_STEP_SUMMARY") {
+        let written = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(format_step_summary(report).as_bytes()));
+        if let Err(e) = written {
+            error!("Failed to write the step summary to {:?}: {:?}", path, e);
+        }
+    }
+    if args.json_output {
+        match serde_json::to_string(report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => error!("Failed to serialize report: {:?}", e),
+        }
+    }
+    if let Some(report_file) = &cfg.report_file {
+        match serde_json::to_string_pretty(report) {
+            Ok(json) => {
+                if let Err(e) = fs::write(report_file, json) {
+                    error!("Failed to write report to {}: {:?}", report_file, e);
+                }
+            }
+            Err(e) => error!("Failed to serialize report: {:?}", e),
         }
+    }
+}
 
-        Ok(Some(pr.clone()))
-    } else {
-        Ok(None)
-    }
-}
-
-async fn dependabot_prs_passing_checks(
-    octo: &Octocrab,
-    repo: &str,
-) -> Result<Vec<DependabotPr>, Box<dyn Error>> {
-    let mut parts = repo.split('/');
-    let org = parts.next().unwrap().to_string();
-    let repo = parts.next().unwrap().to_string();
-
-    let prs = octo
-        .pulls(&org, &repo)
-        .list()
-        .state(params::State::Open)
-        .send()
-        .await?;
-
-    let mut prs_state = Vec::<DependabotPr>::new();
-
-    for pr in prs.into_iter().filter(|pr| {
-        pr.user
-            .as_ref()
-            .map_or(false, |u| u.login == "dependabot[bot]")
-    }) {
-        // octo.checks() does not return all checks for some reason
-        // let checks = octo
-        //     .checks(&org, &repo)
-        //     .list_check_runs_for_git_ref(pr.head.sha.into())
-        //     .send()
-        //     .await?;
-        let checks_url = format!("/repos/{}/{}/commits/{}/check-runs", org, repo, pr.head.sha);
-        let check_runs: octocrab::models::CheckRuns = octo.get(checks_url, None::<&()>).await?;
-
-        let base_branch = octo
-            .repos(&org, &repo)
-            .get_ref(&Reference::Branch(pr.base.ref_field))
-            .await?;
-        let base_branch_sha = match base_branch.object {
-            octocrab::models::repos::Object::Commit { sha, .. } => sha,
-            octocrab::models::repos::Object::Tag { sha, .. } => sha,
-            _ => panic!("main branch is not a commit or tag"),
-        };
+/// A markdown table of what was done to which PR, for the GitHub Actions
+/// job summary.
+fn format_step_summary(report: &RunReport) -> String {
+    let cell = |text: &str| text.replace('|', "\\|");
+    let mut out = format!(
+        "### automerge{}\n\n| Repo | PR | Action | Reason |\n| --- | --- | --- | --- |\n",
+        if report.dry_run { " (dry run)" } else { "" }
+    );
+    for repo in &report.repos {
+        if let Some(error) = &repo.error {
+            out.push_str(&format!("| {} | | error | {} |\n", repo.repo, cell(error)));
+        }
+        if repo.paused {
+            out.push_str(&format!("| {} | | paused | |\n", repo.repo));
+        }
+        for event in &repo.events {
+            let number = event.url.rsplit('/').next().unwrap_or_default();
+            out.push_str(&format!(
+                "| {} | [#{}]({}) | {} | {} |\n",
+                repo.repo,
+                number,
+                event.url,
+                event.action.as_str(),
+                cell(event.reason.as_deref().unwrap_or_default())
+            ));
+        }
+    }
+    out.push('\n');
+    out
+}
 
-        let all_checks_pass = check_runs
-            .check_runs
+/// Lines up the listings in columns.
+fn format_listings(listings: &[PrListing]) -> String {
+    let known = |value: Option<&str>| value.unwrap_or("-").to_string();
+    let yes_no = |value: Option<bool>| known(value.map(|v| if v { "yes" } else { "no" }));
+    let mut rows = vec![[
+        "REPO",
+        "PR",
+        "DEPENDENCY",
+        "VERSION",
+        "REBASED",
+        "CHECKS",
+        "STATUS",
+    ]
+    .map(String::from)];
+    rows.extend(listings.iter().map(|listing| {
+        [
+            listing.repo.clone(),
+            format!("#{}", listing.number),
+            known(listing.dependency.as_deref()),
+            known(listing.version.as_deref()),
+            yes_no(listing.rebased),
+            yes_no(listing.checks_pass),
+            listing.status.clone(),
+        ]
+    }));
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+    let mut table = String::new();
+    for row in rows {
+        let cells: Vec<String> = row
             .iter()
-            .all(|c| c.conclusion != Some("failure".into()));
-
-        let url = format!("/repos/{}/{}/pulls/{}", org, repo, pr.number);
-        let pr: octocrab::models::pulls::PullRequest = octo.get(url, None::<&()>).await?;
-
-        prs_state.push(DependabotPr {
-            url: pr
-                .html_url
-                .map(|url| url.to_string())
-                .unwrap_or("".to_string()),
-            number: pr.number,
-            repo: Repo {
-                org: org.clone(),
-                repo: repo.clone(),
-            },
-            all_checks_pass,
-            rebased: pr.base.sha == base_branch_sha,
-            rebase_in_progress: pr
-                .body
-                .map_or(false, |b| b.contains("Dependabot is rebasing this PR")),
-            new_version: pr
-                .title
-                .and_then(|title| parse_version_from_pr(&title))
-                .unwrap_or("".to_string()),
-        });
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// One line per PR, e.g. `org/repo#12 serde 1.0.200: eligible`.
+fn format_pending(pending: &[PrListing]) -> String {
+    if pending.is_empty() {
+        return "No PRs pending\n".to_string();
+    }
+    let mut out = String::new();
+    for listing in pending {
+        out.push_str(&format!("{}#{}", listing.repo, listing.number));
+        for detail in [&listing.dependency, &listing.version]
+            .into_iter()
+            .flatten()
+        {
+            out.push_str(&format!(" {}", detail));
+        }
+        out.push_str(&format!(": {}\n", listing.status));
     }
+    out
+}
 
-    Ok(prs_state)
+/// One line per check, then a summary.
+fn format_health_checks(checks: &[HealthCheck]) -> String {
+    let mut out = String::new();
+    for check in checks {
+        let status = if check.ok { "OK" } else { "FAIL" };
+        out.push_str(&format!("{:4}  {}: {}\n", status, check.name, check.detail));
+    }
+    let failed = checks.iter().filter(|check| !check.ok).count();
+    out.push_str(&format!(
+        "{} passed, {} failed\n",
+        checks.len() - failed,
+        failed
+    ));
+    out
 }
 
-fn parse_version_from_pr(title: &str) -> Option<String> {
-    let re = Regex::new(r"to (\d+\.\d+\.\d+(-[a-zA-Z0-9\.]+)?(a0)?(\+[a-zA-Z0-9\.]+)?)").unwrap();
-    re.captures(title)
-        .and_then(|captures| captures.get(1).map(|m| m.as_str().to_string()))
+/// Reads and merges the config files, in order. Directories stand for the
+/// `.toml` files in them, sorted by name. Later files override the
+/// settings of earlier ones, tables key by key, except for `repos`, which
+/// are concatenated.
+fn load_config(paths: &[PathBuf]) -> Result<Config, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let entries =
+            fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut tomls = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if entry.path().extension().is_some_and(|ext| ext == "toml") {
+                tomls.push(entry.path());
+            }
+        }
+        tomls.sort();
+        files.extend(tomls);
+    }
+
+    let mut merged = toml::Table::new();
+    for file in files.iter() {
+        let cfg_str = fs::read_to_string(file)
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        let table: toml::Table = toml::from_str(&cfg_str)
+            .map_err(|e| format!("Failed to parse {}: {}", file.display(), e))?;
+        merge_config(&mut merged, table);
+    }
+    let names: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+    toml::Value::Table(merged)
+        .try_into()
+        .map_err(|e| format!("Failed to parse {}: {}", names.join(", "), e))
+}
+
+fn merge_config(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Array(repos)), toml::Value::Array(more)) if key == "repos" => {
+                repos.extend(more);
+            }
+            (Some(toml::Value::Table(table)), toml::Value::Table(more)) => {
+                merge_config(table, more);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Picks `config.toml` in the working directory, falling back to
+/// `$XDG_CONFIG_HOME/merge-dependabot/config.toml` (`~/.config` when unset).
+fn find_config(xdg_config_home: Option<OsString>, home: Option<OsString>) -> PathBuf {
+    let local = PathBuf::from("config.toml");
+    if local.exists() {
+        return local;
+    }
+    let config_home = xdg_config_home
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| Path::new(&home).join(".config")));
+    match config_home {
+        Some(dir) if dir.join("merge-dependabot/config.toml").exists() => {
+            dir.join("merge-dependabot/config.toml")
+        }
+        // Reported as missing by the caller.
+        _ => local,
+    }
+}
+
+/// Resolves on SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            error!("Failed to listen for SIGTERM: {:?}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = sigterm.recv() => {}
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use automerge::{Action, PrEvent, RepoReport};
+    use log::kv::ToValue;
 
     #[test]
-    fn test_parse_version_from_pr() {
+    fn test_parse_args() {
+        let args = |a: &[&str]| Args::parse(a.iter().map(|s| s.to_string()));
+        assert_eq!(args(&[]), Ok(Args::default()));
+        assert_eq!(
+            args(&["--dry-run"]),
+            Ok(Args {
+                dry_run: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["--once"]),
+            Ok(Args {
+                once: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["--watch"]),
+            Ok(Args {
+                watch: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["--watch", "--interval", "60"]),
+            Ok(Args {
+                watch: true,
+                interval: Some(60),
+                ..Args::default()
+            })
+        );
+        assert!(args(&["--once", "--watch"]).is_err());
+        assert!(args(&["--interval", "60"]).is_err());
+        assert_eq!(
+            args(&["--output", "json"]),
+            Ok(Args {
+                json_output: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["--best-effort"]),
+            Ok(Args {
+                best_effort: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["list"]),
+            Ok(Args {
+                list: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["check"]),
+            Ok(Args {
+                check: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["doctor"]),
+            Ok(Args {
+                doctor: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["--pause"]),
+            Ok(Args {
+                pause: true,
+                ..Args::default()
+            })
+        );
+        assert_eq!(
+            args(&["--log-level", "debug"]),
+            Ok(Args {
+                log_level: Some(log::LevelFilter::Debug),
+                ..Args::default()
+            })
+        );
+        assert!(args(&["--log-level", "loud"]).is_err());
         assert_eq!(
-            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4"),
-            Some("1.2.4".to_string())
+            args(&["--log-format", "json"]),
+            Ok(Args {
+                json_logs: true,
+                ..Args::default()
+            })
         );
+        assert!(args(&["--log-format", "xml"]).is_err());
         assert_eq!(
-            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4-alpha"),
-            Some("1.2.4-alpha".to_string())
+            args(&["--config", "/etc/automerge.toml"]),
+            Ok(Args {
+                config: vec![PathBuf::from("/etc/automerge.toml")],
+                ..Args::default()
+            })
         );
         assert_eq!(
-            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4-alpha.1"),
-            Some("1.2.4-alpha.1".to_string())
+            args(&["--config", "a.toml", "--config", "teams"]),
+            Ok(Args {
+                config: vec![PathBuf::from("a.toml"), PathBuf::from("teams")],
+                ..Args::default()
+            })
         );
+        assert!(args(&["--config"]).is_err());
+        assert!(args(&["--output", "yaml"]).is_err());
+        assert!(args(&["--watch", "--interval"]).is_err());
+        assert!(args(&["--watch", "--interval", "soon"]).is_err());
+        assert!(args(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_format_listings() {
+        let listings = [
+            PrListing {
+                repo: "org/repo".to_string(),
+                number: "12".to_string(),
+                dependency: Some("serde".to_string()),
+                version: Some("1.0.200".to_string()),
+                rebased: Some(true),
+                checks_pass: Some(false),
+                status: "skip: checks failing".to_string(),
+                pending: false,
+            },
+            PrListing {
+                repo: "org/repo".to_string(),
+                number: "3".to_string(),
+                dependency: None,
+                version: None,
+                rebased: None,
+                checks_pass: None,
+                status: "skip: draft".to_string(),
+                pending: false,
+            },
+        ];
         assert_eq!(
-            parse_version_from_pr("Bump foo from 1.2.3 to 1.2.4-alpha.1+build.1"),
-            Some("1.2.4-alpha.1+build.1".to_string())
+            format_listings(&listings),
+            "\
+REPO      PR   DEPENDENCY  VERSION  REBASED  CHECKS  STATUS
+org/repo  #12  serde       1.0.200  yes      no      skip: checks failing
+org/repo  #3   -           -        -        -       skip: draft
+"
         );
+    }
+
+    #[test]
+    fn test_format_step_summary() {
+        let report = RunReport {
+            dry_run: true,
+            api_usage: None,
+            repos: vec![
+                RepoReport {
+                    repo: "org/repo".to_string(),
+                    prs_found: 2,
+                    duration_secs: 1.0,
+                    events: vec![
+                        PrEvent {
+                            url: "https://github.com/org/repo/pull/1".to_string(),
+                            action: Action::Merged,
+                            reason: None,
+                        },
+                        PrEvent {
+                            url: "https://github.com/org/repo/pull/2".to_string(),
+                            action: Action::Skipped,
+                            reason: Some("missing label \"a|b\"".to_string()),
+                        },
+                    ],
+                    error: None,
+                    paused: false,
+                    read_only: false,
+                },
+                RepoReport {
+                    repo: "org/other".to_string(),
+                    prs_found: 0,
+                    duration_secs: 0.5,
+                    events: Vec::new(),
+                    error: Some("Not Found".to_string()),
+                    paused: false,
+                    read_only: false,
+                },
+                RepoReport {
+                    paused: true,
+                    ..RepoReport::new("org/paused")
+                },
+            ],
+        };
+        assert_eq!(
+            format_step_summary(&report),
+            "\
+### automerge (dry run)
+
+| Repo | PR | Action | Reason |
+| --- | --- | --- | --- |
+| org/repo | [#1](https://github.com/org/repo/pull/1) | merged |  |
+| org/repo | [#2](https://github.com/org/repo/pull/2) | skipped | missing label \"a\\|b\" |
+| org/other | | error | Not Found |
+| org/paused | | paused | |
+
+"
+        );
+    }
+
+    #[test]
+    fn test_format_pending() {
+        assert_eq!(format_pending(&[]), "No PRs pending\n");
+        let listings = [
+            PrListing {
+                repo: "org/repo".to_string(),
+                number: "12".to_string(),
+                dependency: Some("serde".to_string()),
+                version: Some("1.0.200".to_string()),
+                rebased: Some(true),
+                checks_pass: Some(true),
+                status: "eligible".to_string(),
+                pending: true,
+            },
+            PrListing {
+                repo: "org/other".to_string(),
+                number: "3".to_string(),
+                dependency: Some("tokio, mio".to_string()),
+                version: None,
+                rebased: Some(false),
+                checks_pass: Some(true),
+                status: "skip: not rebased".to_string(),
+                pending: true,
+            },
+        ];
+        assert_eq!(
+            format_pending(&listings),
+            "\
+org/repo#12 serde 1.0.200: eligible
+org/other#3 tokio, mio: skip: not rebased
+"
+        );
+    }
+
+    #[test]
+    fn test_format_health_checks() {
+        let checks = [
+            HealthCheck {
+                name: "API connectivity".to_string(),
+                ok: true,
+                detail: "https://api.github.com".to_string(),
+            },
+            HealthCheck {
+                name: "org/repo: merge PRs".to_string(),
+                ok: false,
+                detail: "no write permission".to_string(),
+            },
+        ];
         assert_eq!(
-            parse_version_from_pr("Bump foo from 1.2.3a0+201.fbdbcb12 to 1.2.3a0+210.bafdcd99"),
-            Some("1.2.3a0+210.bafdcd99".to_string())
+            format_health_checks(&checks),
+            "\
+OK    API connectivity: https://api.github.com
+FAIL  org/repo: merge PRs: no write permission
+1 passed, 1 failed
+"
+        );
+    }
+
+    #[test]
+    fn test_load_config() {
+        let dir = env::temp_dir().join(format!("automerge-configs-{}", std::process::id()));
+        fs::create_dir_all(dir.join("teams")).unwrap();
+        fs::write(
+            dir.join("base.toml"),
+            r#"
+            repos = ["org/a"]
+            min_age_hours = 24
+            version_ceilings = { tokio = "1.999" }
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("teams/2-web.toml"),
+            r#"
+            repos = ["org/c"]
+            min_age_hours = 48
+            version_ceilings = { serde = "1.999" }
+            "#,
         )
+        .unwrap();
+        fs::write(dir.join("teams/1-api.toml"), r#"repos = ["org/b"]"#).unwrap();
+        fs::write(dir.join("teams/README.md"), "not a config").unwrap();
+
+        let cfg = load_config(&[dir.join("base.toml"), dir.join("teams")]).unwrap();
+        let repos: Vec<&str> = cfg.repos.iter().map(|repo| repo.name()).collect();
+        assert_eq!(repos, vec!["org/a", "org/b", "org/c"]);
+        assert_eq!(cfg.min_age_hours, 48);
+        assert_eq!(cfg.version_ceilings.len(), 2);
+
+        assert!(load_config(&[dir.join("missing.toml")]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_log_line() {
+        let fields: &[(&str, kv::Value)] = &[
+            ("repo", "org/repo".to_value()),
+            ("pr", "12".to_value()),
+            ("action", "skip".to_value()),
+            ("reason", None::<&str>.to_value()),
+        ];
+        let record = log::Record::builder()
+            .level(log::Level::Info)
+            .target("automerge")
+            .args(format_args!("[org/repo] skip #12: checks failing"))
+            .key_values(&fields)
+            .build();
+        let now = "2024-05-01T12:00:00Z".parse().unwrap();
+        assert_eq!(
+            json_log_line(&record, now),
+            serde_json::json!({
+                "timestamp": "2024-05-01T12:00:00.000Z",
+                "level": "INFO",
+                "target": "automerge",
+                "message": "[org/repo] skip #12: checks failing",
+                "repo": "org/repo",
+                "pr": "12",
+                "action": "skip",
+                "reason": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_find_config() {
+        // The tests run from the crate root, which has no config.toml.
+        let xdg = env::temp_dir().join(format!("automerge-xdg-{}", std::process::id()));
+        assert_eq!(
+            find_config(Some(xdg.clone().into()), None),
+            PathBuf::from("config.toml")
+        );
+
+        fs::create_dir_all(xdg.join("merge-dependabot")).unwrap();
+        fs::write(xdg.join("merge-dependabot/config.toml"), "repos = []").unwrap();
+        assert_eq!(
+            find_config(Some(xdg.clone().into()), None),
+            xdg.join("merge-dependabot/config.toml")
+        );
+        fs::remove_dir_all(&xdg).unwrap();
     }
 }